@@ -1,15 +1,15 @@
 use sdl2::audio::{AudioQueue, AudioSpecDesired};
+use crate::gbc::GBC;
+use crate::gbc::AudioSink;
 
 pub struct Audio {
     queue: AudioQueue<f32>,
 }
 
 impl Audio {
-    pub const SAMPLE_RATE: i32 = 44100;
-
     pub fn new(sdl_ctx: &sdl2::Sdl) -> Self {
         let desired_spec = AudioSpecDesired {
-            freq: Some(Audio::SAMPLE_RATE),
+            freq: Some(GBC::SAMPLE_RATE as i32),
             channels: Some(2),
             samples: None,
         };
@@ -19,8 +19,10 @@ impl Audio {
         audio.queue.resume();
         audio
     }
+}
 
-    pub fn queue(&self, left_sample: f32, right_sample: f32) {
+impl AudioSink for Audio {
+    fn queue(&mut self, left_sample: f32, right_sample: f32) {
         self.queue.queue(&[left_sample, right_sample]);
     }
 }
@@ -0,0 +1,204 @@
+extern crate sdl2;
+
+mod screen;
+mod audio;
+
+pub use screen::Screen;
+pub use audio::Audio;
+
+use sdl2::event::Event;
+use sdl2::keyboard::Keycode;
+use sdl2::EventPump;
+
+use crate::gbc::{GBC, Button, Movie, Rewind};
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub struct Frontend {
+    sdl_ctx: sdl2::Sdl,
+    event_pump: EventPump,
+    running: bool,
+
+    // Buttons held down, passed to the emulator once per frame so a movie records exactly what it will play back
+    buttons: u8,
+
+    // Save states are kept next to the rom as <rom>.ss0 to <rom>.ss9
+    rom_file: PathBuf,
+    state_slot: u8,
+
+    // Holding backspace runs the game backwards through the recorded history
+    rewind: Rewind,
+    rewinding: bool,
+
+    // I, J, K and L tilt cartridges with an accelerometer up, left, down and right while held, passed on
+    // together with the buttons
+    tilt_keys: [bool; 4],
+}
+
+impl Frontend {
+    pub fn new(rom_file: &Path) -> Self {
+        let sdl_ctx = sdl2::init().unwrap();
+        let event_pump = sdl_ctx.event_pump().unwrap();
+        Frontend {
+            sdl_ctx,
+            event_pump,
+            running: true,
+
+            buttons: 0,
+
+            rom_file: rom_file.to_path_buf(),
+            state_slot: 0,
+
+            rewind: Rewind::new(Rewind::DEFAULT_INTERVAL, Rewind::DEFAULT_CAPACITY),
+            rewinding: false,
+
+            tilt_keys: [false; 4],
+        }
+    }
+
+    pub fn screen(&self, scale: u32) -> Screen {
+        Screen::new(&self.sdl_ctx, scale)
+    }
+
+    pub fn audio(&self) -> Audio {
+        Audio::new(&self.sdl_ctx)
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running
+    }
+
+    // Runs a frame forward, or steps back through the history first while rewinding
+    pub fn run_frame(&mut self, gbc: &mut GBC) {
+        if self.rewinding {
+            self.rewind.rewind(gbc);
+            gbc.run_frame();
+        } else {
+            gbc.run_frame();
+            self.rewind.record(gbc);
+        }
+    }
+
+    // Loading states and rewinding are refused while a movie is active, and the joypad is ignored while one
+    // plays back, as either would make the movie go out of sync
+    pub fn handle_events(&mut self, gbc: &mut GBC, movie: Option<&Movie>) {
+        for event in self.event_pump.poll_iter() {
+            match event {
+                Event::Quit {..} => {
+                    self.running = false;
+                },
+                Event::KeyDown { keycode: Some(Keycode::LCtrl), .. } => {
+                    gbc._rendering_map(true) },
+                /*Event::KeyUp { keycode: Some(Keycode::LCtrl), .. } => {
+                    gbc._rendering_map(false) },*/
+                Event::KeyDown { keycode: Some(Keycode::F8 | Keycode::F9 | Keycode::Backspace), repeat: false, .. } if movie.is_some() => {
+                    eprintln!("Loading states and rewinding are disabled while a movie is active");
+                },
+                Event::KeyDown { keycode: Some(Keycode::F5), repeat: false, .. } => Frontend::save_state(&self.rom_file, self.state_slot, gbc),
+                Event::KeyDown { keycode: Some(Keycode::F8), repeat: false, .. } => Frontend::load_state(&self.rom_file, self.state_slot, gbc),
+                Event::KeyDown { keycode: Some(Keycode::Backspace), .. } if movie.is_none() => self.rewinding = true,
+                Event::KeyUp { keycode: Some(Keycode::Backspace), .. } => self.rewinding = false,
+                Event::KeyDown { keycode: Some(Keycode::F6), repeat: false, .. } => Frontend::export_bess(&self.rom_file, gbc),
+                Event::KeyDown { keycode: Some(Keycode::F9), repeat: false, .. } => Frontend::import_bess(&self.rom_file, gbc),
+                Event::KeyDown { keycode: Some(keycode), repeat: false, .. } => {
+                    if let Some(button) = Frontend::button(keycode) { self.buttons |= button as u8 }
+                    if let Some(key) = Frontend::tilt_key(keycode) { self.tilt_keys[key] = true }
+                    if let Some(slot) = Frontend::state_slot(keycode) {
+                        self.state_slot = slot;
+                        println!("Selected save state slot {}", slot);
+                    }
+                },
+                Event::KeyUp { keycode: Some(keycode), .. } => {
+                    if let Some(button) = Frontend::button(keycode) { self.buttons &= !(button as u8) }
+                    if let Some(key) = Frontend::tilt_key(keycode) { self.tilt_keys[key] = false }
+                },
+                _ => {},
+            }
+        }
+        if !movie.is_some_and(|movie| movie.is_playing()) {
+            let [up, left, down, right] = self.tilt_keys.map(|pressed| pressed as i8 as f32);
+            gbc.set_buttons(self.buttons);
+            gbc.set_tilt(right - left, down - up);
+        }
+    }
+
+    fn state_file(rom_file: &Path, slot: u8) -> PathBuf {
+        rom_file.with_extension(format!("ss{}", slot))
+    }
+
+    fn save_state(rom_file: &Path, slot: u8, gbc: &mut GBC) {
+        match fs::write(Frontend::state_file(rom_file, slot), gbc.save_state()) {
+            Ok(()) => println!("Saved state to slot {}", slot),
+            Err(error) => eprintln!("Could not save state to slot {}: {}", slot, error),
+        }
+    }
+
+    fn load_state(rom_file: &Path, slot: u8, gbc: &mut GBC) {
+        let result = fs::read(Frontend::state_file(rom_file, slot)).map_err(|error| error.to_string())
+            .and_then(|data| gbc.load_state(&data).map_err(|error| error.to_string()));
+        match result {
+            Ok(()) => println!("Loaded state from slot {}", slot),
+            Err(error) => eprintln!("Could not load state from slot {}: {}", slot, error),
+        }
+    }
+
+    // BESS states are shared with other emulators through a single file next to the rom
+    fn export_bess(rom_file: &Path, gbc: &mut GBC) {
+        let bess_file = rom_file.with_extension("bess");
+        match fs::write(&bess_file, gbc.save_bess()) {
+            Ok(()) => println!("Exported BESS state to {}", bess_file.display()),
+            Err(error) => eprintln!("Could not export BESS state: {}", error),
+        }
+    }
+
+    fn import_bess(rom_file: &Path, gbc: &mut GBC) {
+        let bess_file = rom_file.with_extension("bess");
+        let result = fs::read(&bess_file).map_err(|error| error.to_string())
+            .and_then(|data| gbc.load_bess(&data).map_err(|error| error.to_string()));
+        match result {
+            Ok(()) => println!("Imported BESS state from {}", bess_file.display()),
+            Err(error) => eprintln!("Could not import BESS state: {}", error),
+        }
+    }
+
+    fn state_slot(keycode: Keycode) -> Option<u8> {
+        match keycode {
+            Keycode::Num0 => Some(0),
+            Keycode::Num1 => Some(1),
+            Keycode::Num2 => Some(2),
+            Keycode::Num3 => Some(3),
+            Keycode::Num4 => Some(4),
+            Keycode::Num5 => Some(5),
+            Keycode::Num6 => Some(6),
+            Keycode::Num7 => Some(7),
+            Keycode::Num8 => Some(8),
+            Keycode::Num9 => Some(9),
+            _ => None,
+        }
+    }
+
+    fn button(keycode: Keycode) -> Option<Button> {
+        match keycode {
+            Keycode::Down => Some(Button::Down),
+            Keycode::Up => Some(Button::Up),
+            Keycode::Left => Some(Button::Left),
+            Keycode::Right => Some(Button::Right),
+            Keycode::T => Some(Button::Start),
+            Keycode::E => Some(Button::Select),
+            Keycode::S => Some(Button::A),
+            Keycode::A => Some(Button::B),
+            _ => None,
+        }
+    }
+
+    fn tilt_key(keycode: Keycode) -> Option<usize> {
+        match keycode {
+            Keycode::I => Some(0),
+            Keycode::J => Some(1),
+            Keycode::K => Some(2),
+            Keycode::L => Some(3),
+            _ => None,
+        }
+    }
+}
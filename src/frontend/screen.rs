@@ -1,41 +1,42 @@
 extern crate gl;
 
 use sdl2::video::{GLContext, Window, GLProfile, SwapInterval};
-use super::IO;
-use super::super::sdl2::sys;
+use sdl2::sys;
+use crate::gbc::GBC;
+use crate::gbc::VideoSink;
 
 use std::time::{SystemTime, Duration};
+
+const WIDTH: u32 = GBC::SCREEN_WIDTH;
+const HEIGHT: u32 = GBC::SCREEN_HEIGHT;
+
 pub struct Screen {
     _gl_ctx: GLContext,
     window: Window,
     prev_frame_time: SystemTime,
     prev_fps_update_time: SystemTime,
     frames_passed: u32,
-    width: i32,
-    height: i32,
 
     _screen_tex: u32,
     fbo: u32,
-    pub pixels: Vec<u8>,
     frame_period: Duration,
 }
 
 impl Screen {
-    pub const WIDTH: u32 = 160;
-    pub const HEIGHT: u32 = 144;
-    const SCALE: u32 = 3;
-    const GBC_FRAME_PERIOD: Duration = Duration::from_nanos(1e9 as u64 * 114 * 154 / IO::GBC_CLOCK_SPEED as u64);
-    const GB_FRAME_PERIOD: Duration = Duration::from_nanos(1e9 as u64 * 114 * 154 / IO::GB_CLOCK_SPEED as u64);
+    const GBC_FRAME_PERIOD: Duration =
+        Duration::from_nanos(1e9 as u64 * GBC::CYCLES_PER_FRAME as u64 / (2 * GBC::CLOCK_SPEED) as u64);
+    const GB_FRAME_PERIOD: Duration =
+        Duration::from_nanos(1e9 as u64 * GBC::CYCLES_PER_FRAME as u64 / GBC::CLOCK_SPEED as u64);
 
-    pub fn new(sdl_ctx: &sdl2::Sdl) -> Self {
+    pub fn new(sdl_ctx: &sdl2::Sdl, scale: u32) -> Self {
         let video_subsystem = sdl_ctx.video().unwrap();
         
         let gl_attr = video_subsystem.gl_attr();
         gl_attr.set_context_profile(GLProfile::Core);
         gl_attr.set_context_version(3, 3);
 
-        let width = Screen::WIDTH * Screen::SCALE;
-        let height = Screen::HEIGHT * Screen::SCALE;
+        let width = WIDTH * scale;
+        let height = HEIGHT * scale;
         let window = video_subsystem.window("GBC Emulator", width, height).resizable().opengl().build().unwrap();
 
         let gl_ctx = window.gl_create_context().unwrap();
@@ -59,8 +60,8 @@ impl Screen {
             gl::TexParameterfv(gl::TEXTURE_2D, gl::TEXTURE_BORDER_COLOR, &color_black as *const f32);
             gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::NEAREST as i32);
             gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::NEAREST as i32);
-            gl::TexImage2D(gl::TEXTURE_2D, 0, gl::RGBA as i32, Screen::WIDTH as i32,
-                Screen::HEIGHT as i32, 0, gl::RGBA, gl::UNSIGNED_BYTE, std::ptr::null_mut());
+            gl::TexImage2D(gl::TEXTURE_2D, 0, gl::RGBA as i32, WIDTH as i32,
+                HEIGHT as i32, 0, gl::RGBA, gl::UNSIGNED_BYTE, std::ptr::null_mut());
 
             gl::GenFramebuffers(1, &mut fbo as *mut u32);
             gl::BindFramebuffer(gl::READ_FRAMEBUFFER, fbo);
@@ -72,37 +73,34 @@ impl Screen {
             prev_frame_time: SystemTime::now(),
             prev_fps_update_time: SystemTime::now(),
             frames_passed: 0,
-            width: width as i32,
-            height: height as i32,
 
             _screen_tex: screen_tex,
             fbo,
             window,
-            pixels: vec![0; 3 * Screen::WIDTH as usize * Screen::HEIGHT as usize],
             frame_period: Screen::GB_FRAME_PERIOD,
         }
     }
+}
 
-    pub fn set_screen_size(&mut self, width: i32, height: i32) {
-        self.width = width;
-        self.height = height;
-    }
-
-    pub fn render(&mut self) {
-        let (tex_x, tex_y) = if self.width * Screen::HEIGHT as i32 > self.height * Screen::WIDTH as i32 {
-            let scaled_width = (Screen::WIDTH as f32 / Screen::HEIGHT as f32 * self.height as f32) as i32;
-            ((self.width - scaled_width) / 2, 0)
-        } else if self.width * (Screen::HEIGHT as i32) < self.height * Screen::WIDTH as i32 {
-            let scaled_height = (Screen::HEIGHT as f32 / Screen::WIDTH as f32 * self.width as f32) as i32;
-            (0, (self.height - scaled_height) / 2)
+impl VideoSink for Screen {
+    fn present(&mut self, frame: &[u8]) {
+        let (width, height) = self.window.drawable_size();
+        let (width, height) = (width as i32, height as i32);
+        let (tex_x, tex_y) = if width * HEIGHT as i32 > height * WIDTH as i32 {
+            let scaled_width = (WIDTH as f32 / HEIGHT as f32 * height as f32) as i32;
+            ((width - scaled_width) / 2, 0)
+        } else if width * (HEIGHT as i32) < height * WIDTH as i32 {
+            let scaled_height = (HEIGHT as f32 / WIDTH as f32 * width as f32) as i32;
+            (0, (height - scaled_height) / 2)
         } else { (0, 0) };
 
         unsafe {
-            gl::TexSubImage2D(gl::TEXTURE_2D, 0, 0, 0, Screen::WIDTH as i32, Screen::HEIGHT as i32,
-                gl::RGB, gl::UNSIGNED_BYTE, self.pixels.as_ptr() as *const std::ffi::c_void);
+            gl::TexSubImage2D(gl::TEXTURE_2D, 0, 0, 0, WIDTH as i32, HEIGHT as i32,
+                gl::RGBA, gl::UNSIGNED_BYTE, frame.as_ptr() as *const std::ffi::c_void);
             gl::BindFramebuffer(gl::READ_FRAMEBUFFER, self.fbo);
-            gl::BlitFramebuffer(0, 0, Screen::WIDTH as i32, Screen::HEIGHT as i32,
-                tex_x, tex_y, self.width - tex_x, self.height - tex_y, gl::COLOR_BUFFER_BIT, gl::NEAREST);
+            // Frames are top-down, so flip vertically while blitting
+            gl::BlitFramebuffer(0, 0, WIDTH as i32, HEIGHT as i32,
+                tex_x, height - tex_y, width - tex_x, tex_y, gl::COLOR_BUFFER_BIT, gl::NEAREST);
             gl::BindFramebuffer(gl::READ_FRAMEBUFFER, 0);
         }
 
@@ -115,14 +113,14 @@ impl Screen {
         let time_passed = cur_time.duration_since(self.prev_fps_update_time).unwrap().as_secs_f64();
         if time_passed >= 1.0 {
             let fps = self.frames_passed as f64 / time_passed;
-            let audio_latency = unsafe { sys::SDL_GetQueuedAudioSize(2) } as f32 / 8.0 / 44100.0;
+            let audio_latency = unsafe { sys::SDL_GetQueuedAudioSize(2) } as f32 / 8.0 / GBC::SAMPLE_RATE as f32;
             self.window.set_title(&format!("GBC Emulator - {:.2} FPS {:.4}", fps, audio_latency)).unwrap();
             self.frames_passed = 0;
             self.prev_fps_update_time = cur_time;
         }
     }
 
-    pub fn set_double_speed(&mut self, double_speed: bool) {
+    fn set_double_speed(&mut self, double_speed: bool) {
         self.frame_period = if double_speed {
             Screen::GBC_FRAME_PERIOD
         } else {
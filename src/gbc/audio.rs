@@ -0,0 +1,129 @@
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::rc::Rc;
+
+pub const SAMPLE_RATE: u32 = 44100;
+
+// Receives every stereo sample generated by the APU at SAMPLE_RATE
+pub trait AudioSink {
+    fn queue(&mut self, left_sample: f32, right_sample: f32);
+}
+
+// Discards all samples
+pub struct NullSink;
+
+impl AudioSink for NullSink {
+    fn queue(&mut self, _left_sample: f32, _right_sample: f32) {}
+}
+
+// Keeps the most recent samples in memory, dropping the oldest ones once full.
+// Clones share the same buffer, so one clone can be handed to the emulator while
+// another is used to pull the samples.
+#[derive(Clone)]
+pub struct RingBufferSink {
+    samples: Rc<RefCell<VecDeque<(f32, f32)>>>,
+    capacity: usize,
+}
+
+impl RingBufferSink {
+    pub fn new(capacity: usize) -> Self {
+        RingBufferSink {
+            samples: Rc::new(RefCell::new(VecDeque::with_capacity(capacity))),
+            capacity,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.samples.borrow().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.borrow().is_empty()
+    }
+
+    pub fn pop(&self) -> Option<(f32, f32)> {
+        self.samples.borrow_mut().pop_front()
+    }
+
+    // Removes all buffered samples, interleaved as left, right
+    pub fn drain(&self) -> Vec<f32> {
+        let mut samples = self.samples.borrow_mut();
+        let mut interleaved = Vec::with_capacity(2 * samples.len());
+        for (left, right) in samples.drain(..) {
+            interleaved.push(left);
+            interleaved.push(right);
+        }
+        interleaved
+    }
+}
+
+impl AudioSink for RingBufferSink {
+    fn queue(&mut self, left_sample: f32, right_sample: f32) {
+        let mut samples = self.samples.borrow_mut();
+        if samples.len() >= self.capacity { samples.pop_front(); }
+        samples.push_back((left_sample, right_sample));
+    }
+}
+
+// Writes all samples to a 32-bit float stereo WAV file
+pub struct WavSink {
+    writer: BufWriter<File>,
+    sample_count: u32,
+}
+
+impl WavSink {
+    pub fn new<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writer.write_all(&WavSink::header(0))?;
+        Ok(WavSink {
+            writer,
+            sample_count: 0,
+        })
+    }
+
+    fn header(sample_count: u32) -> Vec<u8> {
+        let data_size = sample_count * 8;
+        let mut header = Vec::with_capacity(44);
+        header.extend_from_slice(b"RIFF");
+        header.extend_from_slice(&(36 + data_size).to_le_bytes());
+        header.extend_from_slice(b"WAVEfmt ");
+        header.extend_from_slice(&16u32.to_le_bytes());
+        header.extend_from_slice(&3u16.to_le_bytes()); // IEEE Float
+        header.extend_from_slice(&2u16.to_le_bytes()); // Channels
+        header.extend_from_slice(&SAMPLE_RATE.to_le_bytes());
+        header.extend_from_slice(&(SAMPLE_RATE * 8).to_le_bytes()); // Byte Rate
+        header.extend_from_slice(&8u16.to_le_bytes()); // Block Align
+        header.extend_from_slice(&32u16.to_le_bytes()); // Bits per Sample
+        header.extend_from_slice(b"data");
+        header.extend_from_slice(&data_size.to_le_bytes());
+        header
+    }
+
+    // Writes the final sizes into the header
+    pub fn finish(&mut self) -> io::Result<()> {
+        self.writer.seek(SeekFrom::Start(0))?;
+        self.writer.write_all(&WavSink::header(self.sample_count))?;
+        self.writer.seek(SeekFrom::End(0))?;
+        self.writer.flush()
+    }
+}
+
+impl AudioSink for WavSink {
+    fn queue(&mut self, left_sample: f32, right_sample: f32) {
+        let mut bytes = [0; 8];
+        bytes[..4].copy_from_slice(&left_sample.to_le_bytes());
+        bytes[4..].copy_from_slice(&right_sample.to_le_bytes());
+        if self.writer.write_all(&bytes).is_ok() {
+            self.sample_count += 1;
+        }
+    }
+}
+
+impl Drop for WavSink {
+    fn drop(&mut self) {
+        self.finish().ok();
+    }
+}
@@ -0,0 +1,260 @@
+// Best Effort Save State (BESS) import and export, the block format used by SameBoy and other emulators.
+// Files start with our own state so they round trip exactly, followed by the raw memory regions, the blocks
+// and an 8 byte footer pointing at the first block. Other emulators skip data they don't recognize.
+
+use super::{GBC, Model, StateError};
+
+const FOOTER_MAGIC: &[u8; 4] = b"BESS";
+const CORE_SIZE: usize = 0xD0;
+const RTC_SIZE: usize = 0x30;
+const INFO_SIZE: usize = 0x12;
+const NUM_REGIONS: usize = 7; // WRAM, VRAM, cartridge RAM, OAM, HRAM, BG palettes and OBJ palettes
+
+// Execution states in the CORE block
+const RUNNING: u8 = 0;
+const HALTED: u8 = 1;
+
+struct Core<'a> {
+    model: [u8; 4],
+    register_pairs: [u16; 6],
+    ime: bool,
+    int_enable: u8,
+    execution_state: u8,
+    registers: &'a [u8],
+    regions: [(usize, usize); NUM_REGIONS], // (size, offset)
+}
+
+impl GBC {
+    pub fn save_bess(&mut self) -> Vec<u8> {
+        let mut data = self.save_state();
+
+        let mut regions = [(0, 0); NUM_REGIONS];
+        for (region, memory) in regions.iter_mut().zip(self.io.memory_regions()) {
+            *region = (memory.len(), data.len());
+            data.extend_from_slice(&memory);
+        }
+
+        let first_block = data.len();
+        let name = format!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+        write_block(&mut data, b"NAME", name.as_bytes());
+
+        let mut info = Vec::with_capacity(INFO_SIZE);
+        info.extend((0x0134..0x0144).map(|addr| self.header(addr)));
+        info.extend([self.header(0x014E), self.header(0x014F)]);
+        write_block(&mut data, b"INFO", &info);
+
+        let mut core = Vec::with_capacity(CORE_SIZE);
+        core.extend(1u16.to_le_bytes());
+        core.extend(1u16.to_le_bytes());
+        core.extend(match self.io.model() {
+            Model::Dmg => b"GDB ",
+            Model::Mgb => b"GM  ",
+            Model::Cgb => b"CCE ",
+            Model::Agb => b"CAA ",
+        });
+        for pair in self.cpu.register_pairs().iter() {
+            core.extend(pair.to_le_bytes());
+        }
+        let (ime, is_halted) = self.cpu.interrupt_state();
+        core.extend([ime as u8, self.io.int_enable, if is_halted { HALTED } else { RUNNING }, 0]);
+        core.extend(self.io.registers());
+        for (size, offset) in regions.iter() {
+            core.extend((*size as u32).to_le_bytes());
+            core.extend((*offset as u32).to_le_bytes());
+        }
+        write_block(&mut data, b"CORE", &core);
+
+        let bank_writes = self.io.bank_writes();
+        if !bank_writes.is_empty() {
+            let mbc: Vec<u8> = bank_writes.iter().flat_map(|(addr, value)| {
+                let addr = addr.to_le_bytes();
+                [addr[0], addr[1], *value]
+            }).collect();
+            write_block(&mut data, b"MBC ", &mbc);
+        }
+
+        if let Some(rtc) = self.io.rtc_data() {
+            write_block(&mut data, b"RTC ", &rtc);
+        }
+
+        write_block(&mut data, b"END ", &[]);
+        data.extend((first_block as u32).to_le_bytes());
+        data.extend(FOOTER_MAGIC);
+        data
+    }
+
+    // Loads a BESS file from this or another emulator, leaving the machine untouched if it can't be loaded.
+    // Our own files are restored exactly, others are restored from the blocks as well as the format allows.
+    pub fn load_bess(&mut self, data: &[u8]) -> Result<(), StateError> {
+        if data.len() < 8 || &data[data.len() - 4..] != FOOTER_MAGIC { return Err(StateError::NotAState) }
+        let first_block = read_u32(data, data.len() - 8) as usize;
+
+        let mut core = None;
+        let (mut info, mut mbc, mut rtc) = (None, None, None);
+        let mut pos = first_block;
+        loop {
+            if pos + 8 > data.len() - 8 { return Err(StateError::Truncated) }
+            let id = &data[pos..pos + 4];
+            let len = read_u32(data, pos + 4) as usize;
+            pos += 8;
+            if len > data.len() - 8 - pos { return Err(StateError::Truncated) }
+            let block = &data[pos..pos + len];
+            pos += len;
+
+            // The CORE block always comes first, the other blocks only follow it
+            if core.is_none() && id != b"CORE" && id != b"NAME" && id != b"INFO" { return Err(StateError::NotAState) }
+            match id {
+                b"CORE" => core = Some(parse_core(data, block)?),
+                b"INFO" if len == INFO_SIZE => info = Some(block),
+                b"MBC " if len % 3 == 0 => mbc = Some(block),
+                b"RTC " if len == RTC_SIZE => rtc = Some(block),
+                b"END " => break,
+                _ => {},
+            }
+        }
+        let core = core.ok_or(StateError::NotAState)?;
+
+        // Emulators differ in which revision they emulate, so only the family has to match
+        if (core.model[0] == b'C') != self.io.model().is_cgb() { return Err(StateError::WrongModel) }
+        if let Some(info) = info {
+            let title: Vec<u8> = (0x0134..0x0144).map(|addr| self.header(addr)).collect();
+            if info[..0x10] != title[..] || info[0x10..] != [self.header(0x014E), self.header(0x014F)] {
+                return Err(StateError::WrongCartridge)
+            }
+        }
+
+        // Our own files start with a complete state that ends where the memory regions begin
+        let native_end = core.regions.iter().filter(|(size, _)| *size > 0).map(|(_, offset)| *offset).min();
+        if data.starts_with(&GBC::STATE_MAGIC) && self.load_state(&data[..native_end.unwrap_or(first_block)]).is_ok() {
+            return Ok(())
+        }
+
+        self.save().ok();
+        let regions: Vec<&[u8]> = core.regions.iter().map(|(size, offset)| &data[*offset..offset + size]).collect();
+        self.io.load_memory_regions(&regions);
+        if let Some(mbc) = mbc {
+            for write in mbc.chunks(3) {
+                self.io.write(u16::from_le_bytes([write[0], write[1]]), write[2]);
+            }
+        }
+        self.io.load_registers(core.registers);
+        self.io.int_enable = core.int_enable;
+        self.cpu.set_register_pairs(core.register_pairs);
+        self.cpu.set_interrupt_state(core.ime, core.execution_state == HALTED);
+        if let Some(rtc) = rtc {
+            self.io.load_rtc_data(rtc);
+        }
+        self.state_loaded();
+        Ok(())
+    }
+}
+
+fn parse_core<'a>(data: &[u8], block: &'a [u8]) -> Result<Core<'a>, StateError> {
+    if block.len() < CORE_SIZE { return Err(StateError::Truncated) }
+    let major = u16::from_le_bytes([block[0], block[1]]);
+    if major != 1 { return Err(StateError::UnsupportedVersion(major as u32)) }
+
+    let mut register_pairs = [0; 6];
+    for (i, pair) in register_pairs.iter_mut().enumerate() {
+        *pair = u16::from_le_bytes([block[8 + i * 2], block[9 + i * 2]]);
+    }
+    let mut regions = [(0, 0); NUM_REGIONS];
+    for (i, region) in regions.iter_mut().enumerate() {
+        let (size, offset) = (read_u32(block, 0x98 + i * 8) as usize, read_u32(block, 0x9C + i * 8) as usize);
+        if !offset.checked_add(size).is_some_and(|end| end <= data.len()) { return Err(StateError::Truncated) }
+        *region = (size, offset);
+    }
+
+    Ok(Core {
+        model: [block[4], block[5], block[6], block[7]],
+        register_pairs,
+        ime: block[0x14] != 0,
+        int_enable: block[0x15],
+        execution_state: block[0x16],
+        registers: &block[0x18..0x98],
+        regions,
+    })
+}
+
+fn write_block(data: &mut Vec<u8>, id: &[u8; 4], block: &[u8]) {
+    data.extend(id);
+    data.extend((block.len() as u32).to_le_bytes());
+    data.extend_from_slice(block);
+}
+
+fn read_u32(data: &[u8], pos: usize) -> u32 {
+    u32::from_le_bytes([data[pos], data[pos + 1], data[pos + 2], data[pos + 3]])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::test_rom;
+
+    fn gbc(name: &str) -> GBC {
+        let mut gbc = test_rom::gbc(name, &test_rom::rom(0x01, 0x00, &test_rom::INPUT_LOOP), Model::Dmg);
+        gbc.run_frame();
+        gbc
+    }
+
+    // The contents of the first block with the given id
+    fn block<'a>(data: &'a [u8], id: &[u8; 4]) -> &'a [u8] {
+        let mut pos = read_u32(data, data.len() - 8) as usize;
+        loop {
+            let len = read_u32(data, pos + 4) as usize;
+            if &data[pos..pos + 4] == id { return &data[pos + 8..pos + 8 + len] }
+            pos += 8 + len;
+        }
+    }
+
+    #[test]
+    fn native_file_round_trips() {
+        let mut gbc = gbc("native_file_round_trips");
+        let data = gbc.save_bess();
+        let state = gbc.save_state();
+        gbc.run_frame();
+
+        gbc.load_bess(&data).unwrap();
+        assert_eq!(gbc.save_state(), state);
+    }
+
+    #[test]
+    fn missing_footer_is_refused() {
+        let mut gbc = gbc("missing_footer_is_refused");
+        let data = gbc.save_bess();
+        assert!(matches!(gbc.load_bess(&data[..data.len() - 4]), Err(StateError::NotAState)));
+    }
+
+    #[test]
+    fn oversized_block_is_refused() {
+        let mut gbc = gbc("oversized_block_is_refused");
+        let mut data = gbc.save_bess();
+        let first_block = read_u32(&data, data.len() - 8) as usize;
+        data[first_block + 4..first_block + 8].copy_from_slice(&u32::MAX.to_le_bytes());
+
+        let state = gbc.save_state();
+        assert!(matches!(gbc.load_bess(&data), Err(StateError::Truncated)));
+        assert_eq!(gbc.save_state(), state);
+    }
+
+    // Files from other emulators may only have the required blocks, and never start with our own state
+    #[test]
+    fn foreign_file_loads_from_core_block() {
+        let mut gbc = gbc("foreign_file_loads_from_core_block");
+        let data = gbc.save_bess();
+        let (regions, register_pairs) = (gbc.io.memory_regions(), gbc.cpu.register_pairs());
+
+        let first_block = read_u32(&data, data.len() - 8) as usize;
+        let mut foreign = vec![0; 4];
+        foreign.extend_from_slice(&data[4..first_block]);
+        write_block(&mut foreign, b"CORE", block(&data, b"CORE"));
+        write_block(&mut foreign, b"END ", &[]);
+        foreign.extend((first_block as u32).to_le_bytes());
+        foreign.extend(FOOTER_MAGIC);
+
+        gbc.run_frame();
+        gbc.load_bess(&foreign).unwrap();
+        assert_eq!(gbc.io.memory_regions(), regions);
+        assert_eq!(gbc.cpu.register_pairs(), register_pairs);
+    }
+}
@@ -0,0 +1,194 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+pub const CAMERA_WIDTH: usize = 128;
+pub const CAMERA_HEIGHT: usize = 112;
+
+// Supplies the picture seen by the Pocket Camera sensor whenever it captures
+pub trait CameraSource {
+    // Fills CAMERA_WIDTH * CAMERA_HEIGHT grayscale pixels row by row, 0 is black and 255 is white
+    fn capture(&mut self, image: &mut [u8]);
+}
+
+// Any closure filling the image works as a source
+impl<F: FnMut(&mut [u8])> CameraSource for F {
+    fn capture(&mut self, image: &mut [u8]) {
+        self(image)
+    }
+}
+
+// Lens cap on, every capture is black
+pub struct NoCamera;
+
+impl CameraSource for NoCamera {
+    fn capture(&mut self, image: &mut [u8]) {
+        image.iter_mut().for_each(|pixel| *pixel = 0);
+    }
+}
+
+// The same still image for every capture
+pub struct ImageFile {
+    image: Vec<u8>,
+}
+
+impl ImageFile {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let image = decode_image(&fs::read(path)?)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Unsupported image format, expected PGM, PPM or BMP"))?;
+        Ok(ImageFile { image })
+    }
+}
+
+impl CameraSource for ImageFile {
+    fn capture(&mut self, image: &mut [u8]) {
+        image.copy_from_slice(&self.image);
+    }
+}
+
+// Every image in a directory in file name order, one per capture, starting over after the last one.
+// Files that aren't images are skipped.
+pub struct ImageDirectory {
+    frames: Vec<Vec<u8>>,
+    next_frame: usize,
+}
+
+impl ImageDirectory {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let mut paths = fs::read_dir(path)?.map(|entry| entry.map(|entry| entry.path())).collect::<io::Result<Vec<_>>>()?;
+        paths.sort();
+        let mut frames = Vec::new();
+        for path in paths.iter().filter(|path| path.is_file()) {
+            if let Some(frame) = decode_image(&fs::read(path)?) { frames.push(frame) }
+        }
+        if frames.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "No PGM, PPM or BMP images in the directory"))
+        }
+        Ok(ImageDirectory { frames, next_frame: 0 })
+    }
+}
+
+impl CameraSource for ImageDirectory {
+    fn capture(&mut self, image: &mut [u8]) {
+        image.copy_from_slice(&self.frames[self.next_frame]);
+        self.next_frame = (self.next_frame + 1) % self.frames.len();
+    }
+}
+
+// Decodes a netpbm (P2, P3, P5 or P6) or uncompressed BMP image to grayscale, cropped to the sensor's aspect
+// ratio around the center and scaled to the sensor size
+fn decode_image(data: &[u8]) -> Option<Vec<u8>> {
+    let (width, height, pixels) = if data.starts_with(b"BM") { decode_bmp(data)? } else { decode_netpbm(data)? };
+    if width == 0 || height == 0 { return None }
+
+    let (crop_width, crop_height) = if width * CAMERA_HEIGHT > height * CAMERA_WIDTH {
+        (height * CAMERA_WIDTH / CAMERA_HEIGHT, height)
+    } else {
+        (width, width * CAMERA_HEIGHT / CAMERA_WIDTH)
+    };
+    let (left, top) = ((width - crop_width) / 2, (height - crop_height) / 2);
+    let mut image = vec![0; CAMERA_WIDTH * CAMERA_HEIGHT];
+    for y in 0..CAMERA_HEIGHT {
+        for x in 0..CAMERA_WIDTH {
+            let source_x = left + (x * 2 + 1) * crop_width / (CAMERA_WIDTH * 2);
+            let source_y = top + (y * 2 + 1) * crop_height / (CAMERA_HEIGHT * 2);
+            image[y * CAMERA_WIDTH + x] = pixels[source_y * width + source_x];
+        }
+    }
+    Some(image)
+}
+
+fn luma(r: u32, g: u32, b: u32) -> u8 {
+    ((r * 299 + g * 587 + b * 114) / 1000) as u8
+}
+
+fn decode_netpbm(data: &[u8]) -> Option<(usize, usize, Vec<u8>)> {
+    let (is_color, is_binary) = match data.get(..2)? {
+        b"P2" => (false, false),
+        b"P3" => (true, false),
+        b"P5" => (false, true),
+        b"P6" => (true, true),
+        _ => return None,
+    };
+
+    // Whitespace separated header values, comments run from # to the end of the line
+    let mut pos = 2;
+    let next_value = |pos: &mut usize| -> Option<u32> {
+        loop {
+            match data.get(*pos)? {
+                b'#' => while data.get(*pos)? != &b'\n' { *pos += 1 },
+                byte if byte.is_ascii_whitespace() => *pos += 1,
+                _ => break,
+            }
+        }
+        let start = *pos;
+        while data.get(*pos).is_some_and(|byte| byte.is_ascii_digit()) { *pos += 1 }
+        std::str::from_utf8(&data[start..*pos]).ok()?.parse().ok()
+    };
+    let width = next_value(&mut pos)? as usize;
+    let height = next_value(&mut pos)? as usize;
+    let max_value = next_value(&mut pos)?;
+    if max_value == 0 || max_value > 0xFFFF { return None }
+
+    // Every sample takes at least a byte, so the declared size is checked against the data before allocating
+    let channels = if is_color { 3 } else { 1 };
+    let samples = width.checked_mul(height)?.checked_mul(channels)?;
+    if samples > data.len() { return None }
+    let values = if is_binary {
+        // A single whitespace byte separates the header from the samples
+        let bytes_per_sample = if max_value > 0xFF { 2 } else { 1 };
+        let samples_data = data.get(pos + 1..(pos + 1).checked_add(samples.checked_mul(bytes_per_sample)?)?)?;
+        samples_data.chunks(bytes_per_sample).map(|sample| sample.iter().fold(0, |value, byte| value << 8 | *byte as u32)).collect()
+    } else {
+        (0..samples).map(|_| next_value(&mut pos)).collect::<Option<Vec<_>>>()?
+    };
+
+    let scale = |value: u32| value.min(max_value) * 255 / max_value;
+    let pixels = values.chunks(channels).map(|pixel| match pixel {
+        [r, g, b] => luma(scale(*r), scale(*g), scale(*b)),
+        _ => scale(pixel[0]) as u8,
+    }).collect();
+    Some((width, height, pixels))
+}
+
+// Uncompressed 8 bit paletted, 24 bit and 32 bit bitmaps
+fn decode_bmp(data: &[u8]) -> Option<(usize, usize, Vec<u8>)> {
+    let u16_at = |i: usize| Some(u16::from_le_bytes([*data.get(i)?, *data.get(i + 1)?]) as u32);
+    let u32_at = |i: usize| Some(u16_at(i)? | u16_at(i + 2)? << 16);
+
+    let pixels_offset = u32_at(0x0A)? as usize;
+    let header_size = u32_at(0x0E)? as usize;
+    let width = u32_at(0x12)? as i32;
+    let height = u32_at(0x16)? as i32;
+    let bits_per_pixel = u16_at(0x1C)?;
+    let compression = u32_at(0x1E)?;
+    // Bitfields compression with 32 bit pixels is the usual BGRA layout
+    if width <= 0 || height == 0 || !(compression == 0 || compression == 3 && bits_per_pixel == 32) { return None }
+    if ![8, 24, 32].contains(&bits_per_pixel) { return None }
+
+    // The declared size is checked against the data before allocating
+    let (width, is_top_down) = (width as usize, height < 0);
+    let height = height.unsigned_abs() as usize;
+    let row_size = width.checked_mul(bits_per_pixel as usize)?.div_ceil(32).checked_mul(4)?;
+    if row_size.checked_mul(height)?.checked_add(pixels_offset)? > data.len() { return None }
+    let palette_offset = 0x0E + header_size;
+
+    let mut pixels = Vec::with_capacity(width * height);
+    for y in 0..height {
+        let row_start = pixels_offset + row_size * if is_top_down { y } else { height - 1 - y };
+        let row = data.get(row_start..row_start + row_size)?;
+        for x in 0..width {
+            let bgr = match bits_per_pixel {
+                8 => {
+                    let entry = palette_offset + row[x] as usize * 4;
+                    data.get(entry..entry + 3)?
+                },
+                24 => &row[x * 3..x * 3 + 3],
+                32 => &row[x * 4..x * 4 + 3],
+                _ => return None,
+            };
+            pixels.push(luma(bgr[2] as u32, bgr[1] as u32, bgr[0] as u32));
+        }
+    }
+    Some((width, height, pixels))
+}
@@ -16,6 +16,8 @@ impl CPU {
         self.decode_exec(io, opcode);
     }
 
+    // LD r, r and the untaken branch of RET cc are written out like every other opcode
+    #[allow(dead_code, clippy::self_assignment, clippy::no_effect, clippy::assign_op_pattern)]
     pub fn decode_exec(&mut self, io: &mut IO, opcode: u8) {
         // Register Macros
         macro_rules! get_reg16 { ($high:ident, $low:ident) => { 
@@ -775,6 +777,7 @@ impl CPU {
     }
 
     #[inline]
+    #[allow(clippy::identity_op, clippy::unnecessary_cast)]
     fn inc(&mut self, operand: u8) -> u8{
         let result = operand.wrapping_add(1);
     
@@ -786,6 +789,7 @@ impl CPU {
     }
 
     #[inline]
+    #[allow(clippy::identity_op)]
     fn dec(&mut self, operand: u8) -> u8 {
         let result = operand.wrapping_sub(1);
     
@@ -873,6 +877,7 @@ impl CPU {
     }
 
     #[inline]
+    #[allow(clippy::manual_rotate)]
     fn swap(&mut self, value: u8) -> u8 {
         let return_val = (value << 4) | (value >> 4);
 
@@ -894,6 +899,7 @@ impl CPU {
     }
 
     #[inline]
+    #[allow(clippy::unnecessary_cast)]
     fn rl(&mut self, value: u8) -> u8 {
         let old_c = self.regs.get_flag(Flag::C) as u8;
         self.regs.change_flag(value & 0x80 != 0, Flag::C);
@@ -917,6 +923,7 @@ impl CPU {
     }
 
     #[inline]
+    #[allow(clippy::unnecessary_cast)]
     fn rr(&mut self, value: u8) -> u8 {
         let old_c = self.regs.get_flag(Flag::C) as u8;
         self.regs.change_flag(value & 0x1 == 1, Flag::C);
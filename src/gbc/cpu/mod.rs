@@ -1,12 +1,14 @@
 #[macro_use]
-
 mod registers;
 mod instructions;
 
 use super::IO;
+use super::Model;
+use super::state::{Stateful, State};
 use registers::Registers;
 use registers::Flag;
 
+#[allow(clippy::upper_case_acronyms)]
 pub struct CPU {
     regs: Registers,
     prev_ime: bool,
@@ -35,6 +37,33 @@ impl CPU {
         }
     }
 
+    // Register pairs in BESS order: PC, AF, BC, DE, HL and SP
+    pub fn register_pairs(&self) -> [u16; 6] {
+        let regs = &self.regs;
+        [regs.pc, get_reg16!(regs, a, f), get_reg16!(regs, b, c), get_reg16!(regs, d, e), get_reg16!(regs, h, l), regs.sp]
+    }
+
+    pub fn set_register_pairs(&mut self, pairs: [u16; 6]) {
+        let regs = &mut self.regs;
+        regs.pc = pairs[0];
+        set_reg16!(regs, a, f)(pairs[1] & 0xFFF0);
+        set_reg16!(regs, b, c)(pairs[2]);
+        set_reg16!(regs, d, e)(pairs[3]);
+        set_reg16!(regs, h, l)(pairs[4]);
+        regs.sp = pairs[5];
+    }
+
+    // IME and whether the CPU is halted
+    pub fn interrupt_state(&self) -> (bool, bool) {
+        (self.ime, self.is_halted)
+    }
+
+    pub fn set_interrupt_state(&mut self, ime: bool, is_halted: bool) {
+        self.ime = ime;
+        self.prev_ime = ime;
+        self.is_halted = is_halted;
+    }
+
     pub const INTERRUPT_VECTORS: [u16; 5] = [0x0040, 0x0048, 0x0050, 0x0058, 0x0060];
 
     fn handle_interrupts(&mut self, io: &mut IO) {
@@ -60,9 +89,49 @@ impl CPU {
         self.prev_ime = self.ime;
     }
 
-    pub fn emulate_boot_rom(&mut self, io: &mut IO) {
-        while !io.should_close && self.regs.pc != 0x100 {
-            self.emulate_instr(io);
+    // Sets the registers to the values the boot rom leaves behind and starts at the cartridge entry point
+    pub fn skip_boot_rom(&mut self, io: &IO) {
+        let regs = &mut self.regs;
+        let model = io.model();
+        if !model.is_cgb() {
+            let header_checksum = io.read(0x014D);
+            regs.a = if model == Model::Mgb { 0xFF } else { 0x01 };
+            regs.f = if header_checksum != 0 { 0xB0 } else { 0x80 };
+            set_reg16!(regs, b, c)(0x0013);
+            set_reg16!(regs, d, e)(0x00D8);
+            set_reg16!(regs, h, l)(0x014D);
+        } else {
+            regs.a = 0x11;
+            regs.f = 0x80;
+            if io.in_cgb() {
+                set_reg16!(regs, b, c)(0x0000);
+                set_reg16!(regs, d, e)(0xFF56);
+                set_reg16!(regs, h, l)(0x000D);
+            } else {
+                // B and HL depend on whether the boot rom looked up a palette for a Nintendo title
+                let licensee = io.read(0x014B);
+                let nintendo = licensee == 0x01 || (licensee == 0x33 && io.read(0x0144) == b'0' && io.read(0x0145) == b'1');
+                let title_checksum = (0x0134..0x0144).fold(0u8, |acc, addr| acc.wrapping_add(io.read(addr)));
+                set_reg16!(regs, b, c)(if nintendo { (title_checksum as u16) << 8 } else { 0x0000 });
+                set_reg16!(regs, d, e)(0x0008);
+                set_reg16!(regs, h, l)(if nintendo { 0x991A } else { 0x007C });
+            }
+            if model == Model::Agb {
+                // The AGB boot rom ends with an extra INC B, which games use to detect it
+                regs.b = regs.b.wrapping_add(1);
+                regs.f = if regs.b == 0 { 0x80 } else { 0 } | if regs.b & 0xF == 0 { 0x20 } else { 0 };
+            }
         }
+        regs.sp = 0xFFFE;
+        regs.pc = 0x0100;
+    }
+}
+
+impl Stateful for CPU {
+    fn visit_state(&mut self, state: &mut State) {
+        visit!(state,
+            self.regs.a, self.regs.f, self.regs.b, self.regs.c, self.regs.d, self.regs.e, self.regs.h, self.regs.l,
+            self.regs.sp, self.regs.pc, self.prev_ime, self.ime, self.is_halted,
+        );
     }
 }
@@ -0,0 +1,65 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+#[derive(Debug)]
+pub enum LoadError {
+    Io(PathBuf, io::Error),
+    BadChecksum { expected: u8, actual: u8 },
+    SizeMismatch { expected: usize, actual: usize },
+    UnsupportedRomSize(u8),
+    UnsupportedRamSize(u8),
+    UnsupportedMapper(u8),
+    BadBootRom { size: usize },
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum StateError {
+    NotAState,
+    UnsupportedVersion(u32),
+    WrongModel,
+    WrongCartridge,
+    Truncated,
+    OutOfRange,
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LoadError::Io(path, error) => write!(f, "Could not read {}: {}", path.display(), error),
+            LoadError::BadChecksum { expected, actual } =>
+                write!(f, "Bad header checksum {:02X}, expected {:02X}", actual, expected),
+            LoadError::SizeMismatch { expected, actual } =>
+                write!(f, "ROM is {:#X} bytes, expected {:#X}", actual, expected),
+            LoadError::UnsupportedRomSize(code) => write!(f, "Unsupported ROM size {:02X}", code),
+            LoadError::UnsupportedRamSize(code) => write!(f, "Unsupported RAM size {:02X}", code),
+            LoadError::UnsupportedMapper(cartridge_type) => write!(f, "Unsupported Cartridge Type {:02X}", cartridge_type),
+            LoadError::BadBootRom { size } => write!(f, "Boot ROM is {:#X} bytes, expected 0x100 (DMG) or 0x900 (CGB)", size),
+        }
+    }
+}
+
+impl Error for LoadError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            LoadError::Io(_, error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for StateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StateError::NotAState => write!(f, "Not a save state"),
+            StateError::UnsupportedVersion(version) => write!(f, "Unsupported save state version {}", version),
+            StateError::WrongModel => write!(f, "Save state is for a different hardware model"),
+            StateError::WrongCartridge => write!(f, "Save state is for a different cartridge"),
+            StateError::Truncated => write!(f, "Save state is truncated or corrupt"),
+            StateError::OutOfRange => write!(f, "Save state has values out of range"),
+        }
+    }
+}
+
+impl Error for StateError {}
@@ -0,0 +1,79 @@
+// Per-frame hashes of the output and machine state, for diffing two runs or two builds against each other.
+// FNV-1a is used rather than std's hasher because its output has to stay the same across Rust versions.
+
+use super::GBC;
+
+use std::fmt;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FrameHashes {
+    pub cycles: u128, // Cycles since power on when the hashes were taken
+    pub video: u64,
+    pub audio: u64,
+    pub cpu: u64, // Registers, IME and halt state
+    pub memory: u64, // WRAM, VRAM, cartridge RAM, OAM, HRAM, palettes, IO registers and tilt
+}
+
+impl GBC {
+    // Hashes the last frame and the samples and state it left behind. Call after `run_frame`.
+    pub fn frame_hashes(&mut self) -> FrameHashes {
+        let mut video = Fnv::new();
+        video.write(self.io.frame());
+
+        let mut audio = Fnv::new();
+        for sample in self.io.samples().iter() {
+            audio.write(&sample.to_bits().to_le_bytes());
+        }
+
+        let mut cpu = Fnv::new();
+        for pair in self.cpu.register_pairs().iter() {
+            cpu.write(&pair.to_le_bytes());
+        }
+        let (ime, is_halted) = self.cpu.interrupt_state();
+        cpu.write(&[ime as u8, is_halted as u8]);
+
+        let mut memory = Fnv::new();
+        for region in self.io.memory_regions().iter() {
+            memory.write(region);
+        }
+        memory.write(&self.io.registers());
+        memory.write(&[self.io.int_enable]);
+        let (tilt_x, tilt_y) = self.io.get_tilt();
+        memory.write(&tilt_x.to_bits().to_le_bytes());
+        memory.write(&tilt_y.to_bits().to_le_bytes());
+
+        FrameHashes {
+            cycles: self.io.c,
+            video: video.finish(),
+            audio: audio.finish(),
+            cpu: cpu.finish(),
+            memory: memory.finish(),
+        }
+    }
+}
+
+// One line per frame, so logs can be compared with diff
+impl fmt::Display for FrameHashes {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "cycles={} video={:016x} audio={:016x} cpu={:016x} memory={:016x}",
+            self.cycles, self.video, self.audio, self.cpu, self.memory)
+    }
+}
+
+struct Fnv(u64);
+
+impl Fnv {
+    fn new() -> Self {
+        Fnv(0xCBF29CE484222325)
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes.iter() {
+            self.0 = (self.0 ^ *byte as u64).wrapping_mul(0x100000001B3);
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
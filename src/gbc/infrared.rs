@@ -0,0 +1,73 @@
+use std::cell::Cell;
+use std::rc::Rc;
+
+// Connects the infrared LED and receiver of cartridges that have them, such as HuC1
+pub trait InfraredLink {
+    // Called whenever the cartridge turns its LED on or off
+    fn set_led(&mut self, on: bool);
+
+    // Whether the receiver currently sees light
+    fn receives_light(&self) -> bool;
+}
+
+// Nothing on the other side, no light is ever received
+pub struct NullLink;
+
+impl InfraredLink for NullLink {
+    fn set_led(&mut self, _on: bool) {}
+
+    fn receives_light(&self) -> bool { false }
+}
+
+// Receives the light of its own LED, as if pointed at a mirror
+pub struct Loopback {
+    led: bool,
+}
+
+impl Loopback {
+    pub fn new() -> Self {
+        Loopback { led: false }
+    }
+}
+
+impl Default for Loopback {
+    fn default() -> Self {
+        Loopback::new()
+    }
+}
+
+impl InfraredLink for Loopback {
+    fn set_led(&mut self, on: bool) {
+        self.led = on;
+    }
+
+    fn receives_light(&self) -> bool {
+        self.led
+    }
+}
+
+// One end of a link between two emulators, each end receives the light of the other end's LED
+pub struct CrossLink {
+    own_led: Rc<Cell<bool>>,
+    other_led: Rc<Cell<bool>>,
+}
+
+impl CrossLink {
+    pub fn pair() -> (CrossLink, CrossLink) {
+        let (first_led, second_led) = (Rc::new(Cell::new(false)), Rc::new(Cell::new(false)));
+        (
+            CrossLink { own_led: first_led.clone(), other_led: second_led.clone() },
+            CrossLink { own_led: second_led, other_led: first_led },
+        )
+    }
+}
+
+impl InfraredLink for CrossLink {
+    fn set_led(&mut self, on: bool) {
+        self.own_led.set(on);
+    }
+
+    fn receives_light(&self) -> bool {
+        self.other_led.get()
+    }
+}
@@ -1,3 +1,5 @@
+use super::super::super::state::{Stateful, State};
+
 pub struct Envelope {
     // Registers
     reload: u8, // 4 bit
@@ -63,3 +65,11 @@ impl Envelope {
         self.volume = self.reload;
     }
 }
+
+impl Stateful for Envelope {
+    fn visit_state(&mut self, state: &mut State) {
+        visit!(state,
+            self.reload, self.inc, self.period, self.volume, self.counter, self.done,
+        );
+    }
+}
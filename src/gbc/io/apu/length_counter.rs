@@ -1,3 +1,5 @@
+use super::super::super::state::{Stateful, State};
+
 pub struct LengthCounter {
     length: u16,
     enabled: bool,
@@ -30,4 +32,12 @@ impl LengthCounter {
     pub fn enabled(&self) -> bool {
         self.enabled
     }
-}
\ No newline at end of file
+}
+
+impl Stateful for LengthCounter {
+    fn visit_state(&mut self, state: &mut State) {
+        visit!(state,
+            self.length, self.enabled,
+        );
+    }
+}
@@ -2,7 +2,6 @@ mod tone_sweep;
 mod tone;
 mod wave;
 mod noise;
-mod audio;
 
 mod timer;
 mod length_counter;
@@ -13,9 +12,13 @@ use tone_sweep::ToneSweep;
 use tone::Tone;
 use wave::Wave;
 use noise::Noise;
-use audio::Audio;
 use super::IO;
+use super::super::state::{Stateful, State};
+use super::super::audio::{AudioSink, NullSink, SAMPLE_RATE};
 
+use std::vec::Drain;
+
+#[allow(clippy::upper_case_acronyms)]
 pub struct APU {
     // Registers
     tone_sweep: ToneSweep,
@@ -43,12 +46,17 @@ pub struct APU {
     frame_sequencer_step: u8,
 
     // Audio Output
-    audio: Audio,
+    audio_sink: Box<dyn AudioSink>,
+    samples: Vec<f32>,
     left_sample_sum: f32,
     right_sample_sum: f32,
     sample_count: u32,
     clock_count: f32,
     clocks_per_sample: f32,
+
+    // Sound from the cartridge, which doesn't go through the volume controls
+    cartridge_level: f32,
+    cartridge_sample_sum: f32,
 }
 
 impl MemoryHandler for APU {
@@ -115,7 +123,7 @@ impl MemoryHandler for APU {
 }
 
 impl APU {
-    pub fn new(sdl_ctx: &sdl2::Sdl) -> Self {
+    pub fn new() -> Self {
         APU {
             // Registers
             tone_sweep: ToneSweep::new(),
@@ -143,15 +151,23 @@ impl APU {
             frame_sequencer_step: 0,
 
             // Audio Output
-            audio: Audio::new(sdl_ctx),
+            audio_sink: Box::new(NullSink),
+            samples: Vec::new(),
             left_sample_sum: 0.0,
             right_sample_sum: 0.0,
             sample_count: 0,
             clock_count: 0.0,
             clocks_per_sample: APU::GB_CLOCKS_PER_SAMPLE,
+
+            cartridge_level: 0.0,
+            cartridge_sample_sum: 0.0,
         }
     }
 
+    pub fn set_cartridge_level(&mut self, cartridge_level: f32) {
+        self.cartridge_level = cartridge_level;
+    }
+
     pub fn emulate_clock(&mut self) {
         self.tone_sweep.emulate_clock();
         self.tone.emulate_clock();
@@ -172,6 +188,22 @@ impl APU {
         };
     }
 
+    pub fn set_audio_sink(&mut self, audio_sink: Box<dyn AudioSink>) {
+        self.audio_sink = audio_sink;
+    }
+
+    pub fn drain_samples(&mut self) -> Drain<'_, f32> {
+        self.samples.drain(..)
+    }
+
+    pub fn samples(&self) -> &[f32] {
+        &self.samples
+    }
+
+    pub fn clear_samples(&mut self) {
+        self.samples.clear();
+    }
+
     fn emulate_frame_counter(&mut self) {
         if self.frame_sequencer_counter == 0 {
             self.frame_sequencer_counter = 0x800;
@@ -204,6 +236,7 @@ impl APU {
         self.noise.clock_envelope();
     }
 
+    #[allow(clippy::unnecessary_cast)]
     fn generate_sample(&mut self) {
         if !self.enable_sound { return }
 
@@ -216,6 +249,7 @@ impl APU {
         if self.tone_right_enable { self.right_sample_sum += self.tone.generate_sample(); }
         if self.wave_right_enable { self.right_sample_sum += self.wave.generate_sample(); }
         if self.noise_right_enable { self.right_sample_sum += self.noise.generate_sample(); }
+        self.cartridge_sample_sum += self.cartridge_level;
         
         self.sample_count += 1;
         self.clock_count += 1.0;
@@ -225,17 +259,26 @@ impl APU {
             left_sample *= (self.left_volume + 1) as f32;
             let mut right_sample = self.right_sample_sum / self.sample_count as f32 / 7.5 - 1.0;
             right_sample *= (self.right_volume + 1) as f32;
-            self.audio.queue(APU::VOLUME_FACTOR * left_sample, APU::VOLUME_FACTOR * right_sample);
+            left_sample *= APU::VOLUME_FACTOR;
+            right_sample *= APU::VOLUME_FACTOR;
+            let cartridge_sample = self.cartridge_sample_sum / self.sample_count as f32 * APU::CARTRIDGE_VOLUME;
+            left_sample += cartridge_sample;
+            right_sample += cartridge_sample;
+            self.audio_sink.queue(left_sample, right_sample);
+            self.samples.push(left_sample);
+            self.samples.push(right_sample);
             self.left_sample_sum = 0.0;
             self.right_sample_sum = 0.0;
+            self.cartridge_sample_sum = 0.0;
             self.sample_count = 0;
             self.clock_count -= self.clocks_per_sample;
         }
     }
 
-    const GBC_CLOCKS_PER_SAMPLE: f32 = IO::GBC_CLOCK_SPEED as f32 / Audio::SAMPLE_RATE as f32;
-    const GB_CLOCKS_PER_SAMPLE: f32 = IO::GB_CLOCK_SPEED as f32 / Audio::SAMPLE_RATE as f32;
+    const GBC_CLOCKS_PER_SAMPLE: f32 = IO::GBC_CLOCK_SPEED as f32 / SAMPLE_RATE as f32;
+    const GB_CLOCKS_PER_SAMPLE: f32 = IO::GB_CLOCK_SPEED as f32 / SAMPLE_RATE as f32;
     const VOLUME_FACTOR: f32 = 5e-3;
+    const CARTRIDGE_VOLUME: f32 = 0.1;
 }
 
 trait Channel {
@@ -246,3 +289,20 @@ trait Channel {
     fn generate_sample(&self) -> f32;
     fn playing_sound(&self) -> bool;
 }
+
+impl Stateful for APU {
+    fn visit_state(&mut self, state: &mut State) {
+        visit!(state,
+            self.enable_left_analog, self.left_volume, self.enable_right_analog, self.right_volume,
+            self.noise_left_enable, self.wave_left_enable, self.tone_left_enable, self.tone_sweep_left_enable,
+            self.noise_right_enable, self.wave_right_enable, self.tone_right_enable,
+            self.tone_sweep_right_enable, self.enable_sound, self.frame_sequencer_counter,
+            self.frame_sequencer_step, self.left_sample_sum, self.right_sample_sum, self.sample_count,
+            self.clock_count, self.clocks_per_sample, self.cartridge_sample_sum,
+        );
+        self.tone_sweep.visit_state(state);
+        self.tone.visit_state(state);
+        self.wave.visit_state(state);
+        self.noise.visit_state(state);
+    }
+}
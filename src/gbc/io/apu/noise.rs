@@ -4,6 +4,7 @@ use super::Channel;
 use super::timer::Timer;
 use super::length_counter::LengthCounter;
 use super::envelope::Envelope;
+use super::super::super::state::{Stateful, State};
 
 pub struct Noise {
     // Registers
@@ -112,3 +113,16 @@ impl Noise {
         }
     }
 }
+
+impl Stateful for Noise {
+    fn visit_state(&mut self, state: &mut State) {
+        visit!(state,
+            self.length_reload, self.clock_shift, self.width_mode, self.divisor_code, self.use_length,
+            self.lfsr,
+        );
+        state.check(self.clock_shift < 16 && self.divisor_code < 8);
+        self.envelope.visit_state(state);
+        self.timer.visit_state(state);
+        self.length_counter.visit_state(state);
+    }
+}
@@ -1,3 +1,5 @@
+use super::super::super::state::{Stateful, State};
+
 pub struct Timer {
     counter: u16,
 }
@@ -23,3 +25,11 @@ impl Timer {
         self.counter = reload;
     }
 }
+
+impl Stateful for Timer {
+    fn visit_state(&mut self, state: &mut State) {
+        visit!(state,
+            self.counter,
+        );
+    }
+}
@@ -4,6 +4,7 @@ use super::Channel;
 use super::timer::Timer;
 use super::length_counter::LengthCounter;
 use super::envelope::Envelope;
+use super::super::super::state::{Stateful, State};
 
 pub struct Tone {
     // Registers
@@ -112,3 +113,15 @@ impl Tone {
         }
     }
 }
+
+impl Stateful for Tone {
+    fn visit_state(&mut self, state: &mut State) {
+        visit!(state,
+            self.wave_duty, self.length_reload, self.freq, self.use_length, self.duty_pos,
+        );
+        state.check(self.wave_duty < 4 && self.duty_pos < 8);
+        self.envelope.visit_state(state);
+        self.timer.visit_state(state);
+        self.length_counter.visit_state(state);
+    }
+}
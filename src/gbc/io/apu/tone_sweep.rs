@@ -1,6 +1,7 @@
 use super::MemoryHandler;
 use super::Tone;
 use super::Channel;
+use super::super::super::state::{Stateful, State};
 
 pub struct ToneSweep {
     tone: Tone,
@@ -114,3 +115,13 @@ impl ToneSweep {
         } else { false }
     }
 }
+
+impl Stateful for ToneSweep {
+    fn visit_state(&mut self, state: &mut State) {
+        visit!(state,
+            self.sweep_period, self.sweep_negate, self.sweep_shift, self.sweep_enabled, self.sweep_counter,
+            self.freq_latch, self.enabled,
+        );
+        self.tone.visit_state(state);
+    }
+}
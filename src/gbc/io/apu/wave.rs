@@ -3,6 +3,7 @@ use super::Channel;
 
 use super::timer::Timer;
 use super::length_counter::LengthCounter;
+use super::super::super::state::{Stateful, State};
 
 pub struct Wave {
     // Registers
@@ -115,3 +116,15 @@ impl Wave {
         self.wave_table[addr as usize - 0xFF30] = value;
     }
 }
+
+impl Stateful for Wave {
+    fn visit_state(&mut self, state: &mut State) {
+        visit!(state,
+            self.enabled, self.length_reload, self.output_level, self.freq, self.use_length, self.wave_table,
+            self.wave_pos,
+        );
+        state.check(self.output_level < 4 && self.wave_pos < 32);
+        self.timer.visit_state(state);
+        self.length_counter.visit_state(state);
+    }
+}
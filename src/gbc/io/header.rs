@@ -1,4 +1,4 @@
-use std::str;
+use super::super::LoadError;
 
 pub struct Header {
     _title: String,
@@ -10,29 +10,46 @@ pub struct Header {
 }
 
 impl Header {
-    pub fn new(rom: &Vec<u8>) -> Self {
-        let title = str::from_utf8(&rom[0x134..0x13F]).unwrap().to_string();
+    pub fn new(rom: &[u8]) -> Result<Self, LoadError> {
+        if rom.len() < 0x8000 {
+            return Err(LoadError::SizeMismatch { expected: 0x8000, actual: rom.len() });
+        }
+
+        // MMM01 multicarts start in a menu in the last 32KB of the ROM, which also holds the header for the whole cartridge
+        let menu = &rom[rom.len() - 0x8000..];
+        if rom.len() > 0x8000 && (0x0B..=0x0D).contains(&menu[0x147]) {
+            if let Ok(header) = Header::parse(menu) { return Ok(header) }
+        }
+        Header::parse(rom)
+    }
+
+    fn parse(rom: &[u8]) -> Result<Self, LoadError> {
+        let title = String::from_utf8_lossy(&rom[0x134..0x13F]).trim_end_matches('\0').to_string();
         let supports_cgb = rom[0x143] & 0x80 != 0;
         let supports_sgb = rom[0x146] == 0x03;
         let cartridge_type = rom[0x147];
         let rom_size = rom[0x148];
         let ram_size = rom[0x149];
 
-        let header_checksum = rom[0x14D] as u8;
+        let header_checksum = rom[0x14D];
         let mut x = 0u8;
-        for i in 0x134..0x14D {
-            x = x.wrapping_add(!rom[i]);
+        for byte in &rom[0x134..0x14D] {
+            x = x.wrapping_add(!byte);
+        }
+        if header_checksum != x {
+            return Err(LoadError::BadChecksum { expected: x, actual: header_checksum });
         }
-        assert_eq!(header_checksum, x);
+        if rom_size > 8 { return Err(LoadError::UnsupportedRomSize(rom_size)) }
+        if ram_size > 5 { return Err(LoadError::UnsupportedRamSize(ram_size)) }
 
-        Header {
+        Ok(Header {
             _title: title,
             supports_cgb,
             _supports_sgb: supports_sgb,
             cartridge_type,
             rom_size,
             ram_size,
-        }
+        })
     }
 
     pub fn get_cartridge_type(&self) -> u8 {
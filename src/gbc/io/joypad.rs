@@ -1,21 +1,24 @@
-use sdl2::event::Event;
-use sdl2::keyboard::Keycode;
-
 use super::MemoryHandler;
 use super::IO;
+use super::super::state::{Stateful, State};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Button {
+    A = 0x01,
+    B = 0x02,
+    Select = 0x04,
+    Start = 0x08,
+    Right = 0x10,
+    Left = 0x20,
+    Up = 0x40,
+    Down = 0x80,
+}
 
 pub struct Joypad {
     select_buttons: bool,
     select_dirs: bool,
 
-    dir_down: bool,
-    dir_up: bool,
-    dir_left: bool,
-    dir_right: bool,
-    button_start: bool,
-    button_select: bool,
-    button_a: bool,
-    button_b: bool,
+    pressed: u8, // Button mask, with buttons in the low nibble and directions in the high nibble
 }
 
 impl Joypad {
@@ -23,55 +26,36 @@ impl Joypad {
         Joypad {
             select_buttons: false,
             select_dirs: false,
-            dir_down: false,
-            dir_up: false,
-            dir_left: false,
-            dir_right: false,
-            button_start: false,
-            button_select: false,
-            button_a: false,
-            button_b: false,
+
+            pressed: 0,
         }
     }
 
-    pub fn update_inputs(&mut self, keyboard_events: &Vec<Event>) -> u8 {
+    pub fn set_button(&mut self, button: Button, pressed: bool) -> u8 {
+        let buttons = if pressed { self.pressed | button as u8 } else { self.pressed & !(button as u8) };
+        self.set_buttons(buttons)
+    }
+
+    pub fn set_buttons(&mut self, buttons: u8) -> u8 {
         let old_bits = self.get_bits();
-        for event in keyboard_events {
-            match event {
-                Event::KeyDown { keycode: Some(Keycode::Down), .. } => { self.dir_down = true },
-                Event::KeyDown { keycode: Some(Keycode::Up), .. } => { self.dir_up = true },
-                Event::KeyDown { keycode: Some(Keycode::Left), .. } => { self.dir_left = true },
-                Event::KeyDown { keycode: Some(Keycode::Right), .. } => { self.dir_right = true },
-                Event::KeyDown { keycode: Some(Keycode::T), .. } => { self.button_start = true },
-                Event::KeyDown { keycode: Some(Keycode::E), .. } => { self.button_select = true },
-                Event::KeyDown { keycode: Some(Keycode::S), .. } => { self.button_a = true },
-                Event::KeyDown { keycode: Some(Keycode::A), .. } => { self.button_b = true },
-                Event::KeyUp { keycode: Some(Keycode::Down), .. } => { self.dir_down = false },
-                Event::KeyUp { keycode: Some(Keycode::Up), .. } => { self.dir_up = false },
-                Event::KeyUp { keycode: Some(Keycode::Left), .. } => { self.dir_left = false },
-                Event::KeyUp { keycode: Some(Keycode::Right), .. } => { self.dir_right = false },
-                Event::KeyUp { keycode: Some(Keycode::T), .. } => { self.button_start = false },
-                Event::KeyUp { keycode: Some(Keycode::E), .. } => { self.button_select = false },
-                Event::KeyUp { keycode: Some(Keycode::S), .. } => { self.button_a = false },
-                Event::KeyUp { keycode: Some(Keycode::A), .. } => { self.button_b = false },
-                _ => {},
-            }
-        }
+        self.pressed = buttons;
 
         if old_bits & !self.get_bits() != 0 {
             IO::JOYPAD_INT
         } else { 0 }
     }
 
+    pub fn get_buttons(&self) -> u8 {
+        self.pressed
+    }
+
     fn get_bits(&self) -> u8 {
         let mut input = 0xF;
         if self.select_buttons {
-            input &= !((self.button_start as u8) << 3 | (self.button_select as u8) << 2 |
-                    (self.button_b as u8) << 1 | (self.button_a as u8) << 0);
+            input &= !(self.pressed & 0xF);
         }
         if self.select_dirs {
-            input &= !((self.dir_down as u8) << 3 | (self.dir_up as u8) << 2 |
-                    (self.dir_left as u8) << 1 | (self.dir_right as u8) << 0);
+            input &= !(self.pressed >> 4);
         }
         input
     }
@@ -80,7 +64,7 @@ impl Joypad {
 impl MemoryHandler for Joypad {
     fn read(&self, addr: u16) -> u8 {
         assert_eq!(addr, 0xFF00);
-        return 0xC0 | (!self.select_buttons as u8) << 5 | (!self.select_dirs as u8) << 4 | self.get_bits();
+        0xC0 | (!self.select_buttons as u8) << 5 | (!self.select_dirs as u8) << 4 | self.get_bits()
     }
 
     fn write(&mut self, addr: u16, value: u8) {
@@ -89,3 +73,11 @@ impl MemoryHandler for Joypad {
         self.select_dirs = value & 0x10 == 0;
     }
 }
+
+impl Stateful for Joypad {
+    fn visit_state(&mut self, state: &mut State) {
+        visit!(state,
+            self.select_buttons, self.select_dirs, self.pressed,
+        );
+    }
+}
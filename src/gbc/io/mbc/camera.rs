@@ -0,0 +1,226 @@
+use super::MemoryBankController;
+use super::MemoryHandler;
+use super::Header;
+use super::super::super::camera::{CameraSource, NoCamera, CAMERA_WIDTH, CAMERA_HEIGHT};
+use super::super::super::state::{Stateful, State};
+
+// Pocket Camera mapper with 128KB of RAM and the sensor. Setting bit 4 of the RAM bank maps the sensor
+// registers to 0xA000-0xBFFF, mirrored every 0x80 bytes:
+//   0x00     Bit 0 starts a capture and reads 1 until it is done, only this register can be read
+//   0x01     Bits 0-4 gain, bits 5-6 edge enhancement (bit 5 horizontal, bit 6 vertical), bit 7 shortens captures
+//   0x02-03  Exposure time, most significant byte first
+//   0x04     Bits 4-6 edge enhancement ratio
+//   0x06-35  4x4 dithering matrix, 3 thresholds per pixel from dark to light
+// Captures are written to RAM bank 0 at 0xA100 as 16x14 tiles, and RAM reads 0x00 while capturing.
+pub struct Camera {
+    rom_mask: usize,
+    ram_mask: usize,
+
+    rom: Vec<u8>,
+    rom_bank: usize,
+    ram_bank: usize,
+    ram_enable: bool,
+    registers_mapped: bool,
+    external_ram: Vec<u8>,
+    is_save_dirty: bool, // Battery backed storage changed since the last `take_save_dirty`
+
+    registers: [u8; Camera::NUM_REGISTERS],
+    capture_cycles: u32, // Machine cycles left until the capture in progress is done
+    source: Box<dyn CameraSource>,
+}
+
+impl Camera {
+    pub fn new(header: Header, rom: Vec<u8>) -> Self {
+        let ram_size = header.get_ram_size();
+        let rom_size = header.get_rom_size();
+        Camera {
+            rom_mask: rom_size / 0x4000 - 1,
+            ram_mask: if ram_size <= 0x2000 { 0 } else { ram_size / 0x2000 - 1 },
+
+            rom,
+            rom_bank: 1,
+            ram_bank: 0,
+            ram_enable: false,
+            registers_mapped: false,
+            external_ram: vec![0; ram_size],
+            is_save_dirty: false,
+
+            registers: [0; Camera::NUM_REGISTERS],
+            capture_cycles: 0,
+            source: Box::new(NoCamera),
+        }
+    }
+
+    const NUM_REGISTERS: usize = 0x36;
+    const DITHERING_MATRIX: usize = 0x06;
+    const IMAGE_ADDR: usize = 0x100;
+    const EDGE_RATIOS: [f32; 8] = [0.5, 0.75, 1.0, 1.25, 2.0, 3.0, 4.0, 5.0];
+
+    fn ram_addr(&self, addr: u16) -> Option<usize> {
+        if self.external_ram.is_empty() { return None }
+        Some((self.ram_bank * 0x2000 + (addr as usize - 0xA000)) % self.external_ram.len())
+    }
+
+    fn exposure(&self) -> u32 {
+        (self.registers[0x02] as u32) << 8 | self.registers[0x03] as u32
+    }
+
+    fn capture_duration(&self) -> u32 {
+        32446 + if self.registers[0x01] & 0x80 != 0 { 0 } else { 512 } + 16 * self.exposure()
+    }
+
+    // Runs the picture from the source through the sensor and writes the dithered tiles to RAM
+    fn capture(&mut self) {
+        if self.external_ram.len() < Camera::IMAGE_ADDR + CAMERA_WIDTH * CAMERA_HEIGHT / 4 { return }
+        let mut image = vec![0; CAMERA_WIDTH * CAMERA_HEIGHT];
+        self.source.capture(&mut image);
+
+        // Output scales with exposure time, 0x1000 passes the picture through unchanged, and with the gain.
+        // Gain grows about linearly from 0.9 to 1.68, where the default setting of 4 is 1.
+        let gain = 1.0 + (self.registers[0x01] & 0x1F) as f32 * 0.025 - 0.1;
+        let scale = gain * self.exposure() as f32 / 0x1000 as f32;
+        let levels = image.iter().map(|pixel| *pixel as f32 * scale).collect::<Vec<_>>();
+        let level = |x: usize, y: usize| levels[y * CAMERA_WIDTH + x];
+
+        let edge_ratio = Camera::EDGE_RATIOS[(self.registers[0x04] >> 4) as usize & 0x7];
+        let (horizontal_edges, vertical_edges) = (self.registers[0x01] & 0x20 != 0, self.registers[0x01] & 0x40 != 0);
+
+        for y in 0..CAMERA_HEIGHT {
+            for x in 0..CAMERA_WIDTH {
+                // Edge enhancement adds the difference to the neighbours, which are clamped to the picture
+                let mut value = level(x, y);
+                let mut neighbours = Vec::with_capacity(4);
+                if horizontal_edges {
+                    neighbours.push(level(x.saturating_sub(1), y));
+                    neighbours.push(level((x + 1).min(CAMERA_WIDTH - 1), y));
+                }
+                if vertical_edges {
+                    neighbours.push(level(x, y.saturating_sub(1)));
+                    neighbours.push(level(x, (y + 1).min(CAMERA_HEIGHT - 1)));
+                }
+                value += neighbours.iter().map(|neighbour| value - neighbour).sum::<f32>() * edge_ratio;
+
+                // Below the first threshold is black, above the last one white
+                let thresholds = Camera::DITHERING_MATRIX + ((y & 3) * 4 + (x & 3)) * 3;
+                let color = 3 - self.registers[thresholds..thresholds + 3].iter()
+                    .take_while(|threshold| value >= **threshold as f32).count() as u8;
+
+                let addr = Camera::IMAGE_ADDR + ((y / 8) * (CAMERA_WIDTH / 8) + x / 8) * 16 + (y % 8) * 2;
+                let bit = 0x80 >> (x % 8);
+                for plane in 0..2 {
+                    if color & (1 << plane) != 0 {
+                        self.external_ram[addr + plane] |= bit;
+                    } else {
+                        self.external_ram[addr + plane] &= !bit;
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl MemoryHandler for Camera {
+    fn read(&self, addr: u16) -> u8 {
+        match addr & 0xC000 {
+            0x0000 => self.rom[addr as usize],
+            0x4000 => self.rom[self.rom_bank * 0x4000 + (addr - 0x4000) as usize],
+            0x8000 => if self.registers_mapped {
+                if addr & 0x7F == 0 { self.registers[0] } else { 0x00 }
+            } else if self.capture_cycles > 0 {
+                0x00
+            } else {
+                // Unlike other mappers, RAM reads work without `ram_enable`, which only guards writes
+                self.ram_addr(addr).map_or(0xFF, |addr| self.external_ram[addr])
+            },
+            _ => panic!("Shouldn't be here!"),
+        }
+    }
+
+    fn write(&mut self, addr: u16, value: u8) {
+        match addr & 0xE000 {
+            0x0000 => self.ram_enable = value & 0x0F == 0x0A,
+            0x2000 => self.rom_bank = (value as usize & 0x3F) & self.rom_mask,
+            0x4000 => {
+                self.registers_mapped = value & 0x10 != 0;
+                self.ram_bank = (value as usize & 0x0F) & self.ram_mask;
+            },
+            0x6000 => {},
+            0xA000 => if self.registers_mapped {
+                match addr as usize & 0x7F {
+                    0x00 => {
+                        if value & 0x01 == 0 {
+                            self.capture_cycles = 0;
+                        } else if self.capture_cycles == 0 {
+                            self.capture_cycles = self.capture_duration();
+                        }
+                        self.registers[0] = value & 0x07;
+                    },
+                    register if register < Camera::NUM_REGISTERS => self.registers[register] = value,
+                    _ => {},
+                }
+            } else if self.ram_enable && self.capture_cycles == 0 {
+                if let Some(addr) = self.ram_addr(addr) {
+                    self.external_ram[addr] = value;
+                    self.is_save_dirty = true;
+                }
+            },
+            _ => panic!("Shouldn't be here!"),
+        }
+    }
+}
+
+impl MemoryBankController for Camera {
+    fn emulate_clock(&mut self) {
+        if self.capture_cycles > 0 {
+            self.capture_cycles -= 1;
+            if self.capture_cycles == 0 {
+                self.capture();
+                self.registers[0] &= !0x01;
+                self.is_save_dirty = true;
+            }
+        }
+    }
+
+    fn save_data(&self) -> Option<Vec<u8>> {
+        Some(self.external_ram.to_vec())
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) {
+        let len = data.len().min(self.external_ram.len());
+        self.external_ram[..len].copy_from_slice(&data[..len]);
+    }
+
+    fn take_save_dirty(&mut self) -> bool {
+        std::mem::replace(&mut self.is_save_dirty, false)
+    }
+
+    fn external_ram(&mut self) -> &mut [u8] {
+        &mut self.external_ram
+    }
+
+    // The sensor registers are restored too, except for the one starting a capture
+    fn bank_writes(&self) -> Vec<(u16, u8)> {
+        let mut writes = vec![(0x4000, 0x10)];
+        writes.extend((1..Camera::NUM_REGISTERS).map(|register| (0xA000 + register as u16, self.registers[register])));
+        writes.extend_from_slice(&[
+            (0x0000, if self.ram_enable { 0x0A } else { 0x00 }),
+            (0x2000, self.rom_bank as u8),
+            (0x4000, self.ram_bank as u8 | if self.registers_mapped { 0x10 } else { 0x00 }),
+        ]);
+        writes
+    }
+
+    fn set_camera_source(&mut self, source: Box<dyn CameraSource>) {
+        self.source = source;
+    }
+}
+
+impl Stateful for Camera {
+    fn visit_state(&mut self, state: &mut State) {
+        visit!(state,
+            self.rom_bank, self.ram_bank, self.ram_enable, self.registers_mapped, self.external_ram[..],
+            self.registers, self.capture_cycles,
+        );
+        state.check(self.rom_bank <= self.rom_mask && self.ram_bank <= self.ram_mask);
+    }
+}
@@ -0,0 +1,129 @@
+use super::MemoryBankController;
+use super::MemoryHandler;
+use super::Header;
+use super::super::super::infrared::{InfraredLink, NullLink};
+use super::super::super::state::{Stateful, State};
+
+// Hudson's mapper with an infrared LED and receiver. Writing 0x0E to 0x0000-0x1FFF maps the IR register to
+// 0xA000-0xBFFF instead of RAM, which reads 0xC1 while light is received and turns the LED on with bit 0.
+pub struct HuC1 {
+    rom_mask: usize,
+    ram_mask: usize,
+
+    rom: Vec<u8>,
+    rom_bank: usize,
+    ram_bank: usize,
+    is_ir_mode: bool,
+    external_ram: Vec<u8>,
+    is_save_dirty: bool, // Battery backed storage changed since the last `take_save_dirty`
+
+    led_on: bool,
+    infrared_link: Box<dyn InfraredLink>,
+}
+
+impl HuC1 {
+    pub fn new(header: Header, rom: Vec<u8>) -> Self {
+        let ram_size = header.get_ram_size();
+        let rom_size = header.get_rom_size();
+        HuC1 {
+            rom_mask: rom_size / 0x4000 - 1,
+            ram_mask: if ram_size <= 0x2000 { 0 } else { ram_size / 0x2000 - 1 },
+
+            rom,
+            rom_bank: 1,
+            ram_bank: 0,
+            is_ir_mode: false,
+            external_ram: vec![0; ram_size],
+            is_save_dirty: false,
+
+            led_on: false,
+            infrared_link: Box::new(NullLink),
+        }
+    }
+
+    fn ram_addr(&self, addr: u16) -> Option<usize> {
+        if self.external_ram.is_empty() { return None }
+        Some((self.ram_bank * 0x2000 + (addr as usize - 0xA000)) % self.external_ram.len())
+    }
+}
+
+impl MemoryHandler for HuC1 {
+    fn read(&self, addr: u16) -> u8 {
+        match addr & 0xC000 {
+            0x0000 => self.rom[addr as usize],
+            0x4000 => self.rom[self.rom_bank * 0x4000 + (addr - 0x4000) as usize],
+            0x8000 => if self.is_ir_mode {
+                0xC0 | self.infrared_link.receives_light() as u8
+            } else {
+                self.ram_addr(addr).map_or(0xFF, |addr| self.external_ram[addr])
+            },
+            _ => panic!("Shouldn't be here!"),
+        }
+    }
+
+    fn write(&mut self, addr: u16, value: u8) {
+        match addr & 0xE000 {
+            0x0000 => self.is_ir_mode = value & 0x0F == 0x0E,
+            0x2000 => self.rom_bank = (value as usize & 0x3F) & self.rom_mask,
+            0x4000 => self.ram_bank = (value as usize & 0x3) & self.ram_mask,
+            0x6000 => {},
+            0xA000 => if self.is_ir_mode {
+                let led_on = value & 0x1 != 0;
+                if led_on != self.led_on {
+                    self.led_on = led_on;
+                    self.infrared_link.set_led(led_on);
+                }
+            } else if let Some(addr) = self.ram_addr(addr) {
+                self.external_ram[addr] = value;
+                self.is_save_dirty = true;
+            },
+            _ => panic!("Shouldn't be here!"),
+        }
+    }
+}
+
+impl MemoryBankController for HuC1 {
+    fn emulate_clock(&mut self) {}
+
+    fn save_data(&self) -> Option<Vec<u8>> {
+        Some(self.external_ram.to_vec())
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) {
+        let len = data.len().min(self.external_ram.len());
+        self.external_ram[..len].copy_from_slice(&data[..len]);
+    }
+
+    fn take_save_dirty(&mut self) -> bool {
+        std::mem::replace(&mut self.is_save_dirty, false)
+    }
+
+    fn external_ram(&mut self) -> &mut [u8] {
+        &mut self.external_ram
+    }
+
+    fn bank_writes(&self) -> Vec<(u16, u8)> {
+        vec![
+            (0x2000, self.rom_bank as u8),
+            (0x4000, self.ram_bank as u8),
+            (0x0000, if self.is_ir_mode { 0x0E } else { 0x00 }),
+        ]
+    }
+
+    fn set_infrared_link(&mut self, infrared_link: Box<dyn InfraredLink>) {
+        self.infrared_link = infrared_link;
+        self.infrared_link.set_led(self.led_on);
+    }
+}
+
+impl Stateful for HuC1 {
+    fn visit_state(&mut self, state: &mut State) {
+        let mut led_on = self.led_on;
+        visit!(state, self.rom_bank, self.ram_bank, self.is_ir_mode, led_on, self.external_ram[..]);
+        state.check(self.rom_bank <= self.rom_mask);
+        if state.is_loading() && led_on != self.led_on {
+            self.led_on = led_on;
+            self.infrared_link.set_led(led_on);
+        }
+    }
+}
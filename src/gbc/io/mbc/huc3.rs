@@ -0,0 +1,329 @@
+use super::MemoryBankController;
+use super::MemoryHandler;
+use super::Header;
+use super::unix_time;
+use super::super::super::infrared::{InfraredLink, NullLink};
+use super::super::super::state::{Stateful, State};
+
+use std::convert::TryInto;
+
+// Hudson's mapper with a clock, a speaker and an infrared LED. The low nibble written to 0x0000-0x1FFF picks
+// what 0xA000-0xBFFF maps to: RAM (0x0 read only, 0xA read/write), the command register (0xB), the command
+// response (0xC), the command semaphore (0xD) or the IR register (0xE).
+//
+// Commands are a command nibble followed by an argument nibble and work on a nibble-addressed memory:
+// 0x1 reads a nibble into the response and increments the address, 0x3 writes and increments,
+// 0x4 and 0x5 set the low and high nibble of the address and 0x6 runs an extended command, others do nothing.
+// Nibbles 0x00-0x02 hold the minute of the day and 0x03-0x05 the 12 bit day counter.
+pub struct HuC3 {
+    rom_mask: usize,
+    ram_mask: usize,
+
+    rom: Vec<u8>,
+    rom_bank: usize,
+    ram_bank: usize,
+    mode: u8,
+    external_ram: Vec<u8>,
+    is_save_dirty: bool, // Battery backed storage changed since the last `take_save_dirty`
+
+    // Clock
+    memory: [u8; 0x100], // Nibbles that aren't part of the clock, including the alarm at 0x58-0x5F
+    address: u8,
+    response: u8,
+    extended_command: u8,
+    minutes: u16,
+    days: u16, // Only the low 12 bits are used
+    clock_counter: u32, // Machine cycles into the current minute
+
+    // Speaker
+    tone_cycles: u32, // Machine cycles left in the tone playing
+    tone_counter: u32,
+    tone_half_period: u32,
+    speaker_high: bool,
+
+    led_on: bool,
+    infrared_link: Box<dyn InfraredLink>,
+}
+
+impl HuC3 {
+    pub fn new(header: Header, rom: Vec<u8>) -> Self {
+        let ram_size = header.get_ram_size();
+        let rom_size = header.get_rom_size();
+        HuC3 {
+            rom_mask: rom_size / 0x4000 - 1,
+            ram_mask: if ram_size <= 0x2000 { 0 } else { ram_size / 0x2000 - 1 },
+
+            rom,
+            rom_bank: 1,
+            ram_bank: 0,
+            mode: 0,
+            external_ram: vec![0; ram_size],
+            is_save_dirty: false,
+
+            memory: [0; 0x100],
+            address: 0,
+            response: 0,
+            extended_command: 0,
+            minutes: 0,
+            days: 0,
+            clock_counter: 0,
+
+            tone_cycles: 0,
+            tone_counter: 0,
+            tone_half_period: 0,
+            speaker_high: false,
+
+            led_on: false,
+            infrared_link: Box::new(NullLink),
+        }
+    }
+
+    const CYCLES_PER_SECOND: u32 = 1 << 20;
+    const MINUTES_PER_DAY: u16 = 24 * 60;
+    const DAYS_MASK: u16 = 0xFFF;
+    const RTC_SIZE: usize = 17;
+    // Extended command 0xE plays the tone picked by nibble 0x27 for a quarter of a second
+    const TONE_FREQUENCIES: [u32; 4] = [2048, 1024, 4096, 512];
+    const TONE_CYCLES: u32 = HuC3::CYCLES_PER_SECOND / 4;
+
+    fn ram_addr(&self, addr: u16) -> Option<usize> {
+        if self.external_ram.is_empty() { return None }
+        Some((self.ram_bank * 0x2000 + (addr as usize - 0xA000)) % self.external_ram.len())
+    }
+
+    fn read_nibble(&self, address: u8) -> u8 {
+        match address {
+            0x00..=0x02 => (self.minutes >> (address * 4)) as u8 & 0xF,
+            0x03..=0x05 => (self.days >> ((address - 0x03) * 4)) as u8 & 0xF,
+            _ => self.memory[address as usize],
+        }
+    }
+
+    fn write_nibble(&mut self, address: u8, value: u8) {
+        let value = value as u16 & 0xF;
+        match address {
+            0x00..=0x02 => {
+                let shift = address * 4;
+                self.minutes = (self.minutes & !(0xF << shift) | value << shift) % HuC3::MINUTES_PER_DAY;
+            },
+            0x03..=0x05 => {
+                let shift = (address - 0x03) * 4;
+                self.days = self.days & !(0xF << shift) | value << shift;
+            },
+            _ => self.memory[address as usize] = value as u8,
+        }
+    }
+
+    fn run_command(&mut self, value: u8) {
+        let argument = value & 0xF;
+        match value >> 4 {
+            0x1 => {
+                self.response = self.read_nibble(self.address);
+                self.address = self.address.wrapping_add(1);
+            },
+            0x3 => {
+                // The clock and alarm are saved with the RAM
+                self.write_nibble(self.address, argument);
+                self.is_save_dirty = true;
+                self.address = self.address.wrapping_add(1);
+            },
+            0x4 => self.address = self.address & 0xF0 | argument,
+            0x5 => self.address = self.address & 0x0F | argument << 4,
+            0x6 => {
+                self.extended_command = argument;
+                // Extended command 0x2 is a handshake answered with 1
+                if argument == 0x2 { self.response = 0x1 }
+                if argument == 0xE {
+                    let frequency = HuC3::TONE_FREQUENCIES[self.memory[0x27] as usize & 0x3];
+                    self.tone_half_period = HuC3::CYCLES_PER_SECOND / frequency / 2;
+                    self.tone_cycles = HuC3::TONE_CYCLES;
+                    self.tone_counter = 0;
+                }
+            },
+            _ => {},
+        }
+    }
+
+    fn advance_minutes(&mut self, minutes: u64) {
+        let total = self.minutes as u64 + minutes;
+        self.minutes = (total % HuC3::MINUTES_PER_DAY as u64) as u16;
+        self.days = self.days.wrapping_add((total / HuC3::MINUTES_PER_DAY as u64) as u16) & HuC3::DAYS_MASK;
+    }
+
+    // Alarm minutes and days, kept in the nibble memory
+    fn alarm(&self) -> (u16, u16) {
+        let nibbles = |start: usize, len: usize| {
+            self.memory[start..start + len].iter().rev().fold(0, |value, nibble| value << 4 | *nibble as u16)
+        };
+        (nibbles(0x58, 3), nibbles(0x5B, 3))
+    }
+
+    fn set_alarm(&mut self, minutes: u16, days: u16) {
+        for i in 0..3 { self.memory[0x58 + i] = (minutes >> (i * 4)) as u8 & 0xF }
+        for i in 0..3 { self.memory[0x5B + i] = (days >> (i * 4)) as u8 & 0xF }
+    }
+}
+
+impl MemoryHandler for HuC3 {
+    fn read(&self, addr: u16) -> u8 {
+        match addr & 0xC000 {
+            0x0000 => self.rom[addr as usize],
+            0x4000 => self.rom[self.rom_bank * 0x4000 + (addr - 0x4000) as usize],
+            0x8000 => match self.mode {
+                0x0 | 0xA => self.ram_addr(addr).map_or(0xFF, |addr| self.external_ram[addr]),
+                0xC => self.response,
+                0xD => 0x1, // Commands run immediately, so they are always done
+                0xE => 0xC0 | self.infrared_link.receives_light() as u8,
+                _ => 0xFF,
+            },
+            _ => panic!("Shouldn't be here!"),
+        }
+    }
+
+    fn write(&mut self, addr: u16, value: u8) {
+        match addr & 0xE000 {
+            0x0000 => self.mode = value & 0xF,
+            0x2000 => self.rom_bank = (value as usize & 0x7F) & self.rom_mask,
+            0x4000 => self.ram_bank = (value as usize & 0x3) & self.ram_mask,
+            0x6000 => {},
+            0xA000 => match self.mode {
+                0xA => if let Some(addr) = self.ram_addr(addr) {
+                    self.external_ram[addr] = value;
+                    self.is_save_dirty = true;
+                },
+                0xB => self.run_command(value),
+                0xE => {
+                    let led_on = value & 0x1 != 0;
+                    if led_on != self.led_on {
+                        self.led_on = led_on;
+                        self.infrared_link.set_led(led_on);
+                    }
+                },
+                _ => {},
+            },
+            _ => panic!("Shouldn't be here!"),
+        }
+    }
+}
+
+impl MemoryBankController for HuC3 {
+    fn emulate_clock(&mut self) {
+        self.clock_counter += 1;
+        if self.clock_counter == 60 * HuC3::CYCLES_PER_SECOND {
+            self.clock_counter = 0;
+            self.advance_minutes(1);
+        }
+
+        if self.tone_cycles > 0 {
+            self.tone_cycles -= 1;
+            self.tone_counter += 1;
+            if self.tone_counter >= self.tone_half_period {
+                self.tone_counter = 0;
+                self.speaker_high = !self.speaker_high;
+            }
+        }
+    }
+
+    // RAM followed by the clock in the 17 byte layout SameBoy uses: timestamp, minutes, days,
+    // alarm minutes, alarm days and alarm enable
+    fn save_data(&self) -> Option<Vec<u8>> {
+        let mut data = self.external_ram.to_vec();
+        let (alarm_minutes, alarm_days) = self.alarm();
+        // The timestamp is moved back by the part of the minute that passed, so it isn't lost
+        let timestamp = unix_time().saturating_sub((self.clock_counter / HuC3::CYCLES_PER_SECOND) as u64);
+        data.extend_from_slice(&timestamp.to_le_bytes());
+        data.extend_from_slice(&self.minutes.to_le_bytes());
+        data.extend_from_slice(&self.days.to_le_bytes());
+        data.extend_from_slice(&alarm_minutes.to_le_bytes());
+        data.extend_from_slice(&alarm_days.to_le_bytes());
+        data.push(self.memory[0x5F] & 0x1);
+        Some(data)
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) {
+        let ram_len = data.len().min(self.external_ram.len());
+        self.external_ram[..ram_len].copy_from_slice(&data[..ram_len]);
+
+        let rtc = &data[ram_len..];
+        if rtc.len() != HuC3::RTC_SIZE { return }
+        let u16_at = |i: usize| u16::from_le_bytes(rtc[i..i + 2].try_into().unwrap());
+        self.minutes = u16_at(8) % HuC3::MINUTES_PER_DAY;
+        self.days = u16_at(10) & HuC3::DAYS_MASK;
+        self.set_alarm(u16_at(12), u16_at(14));
+        self.memory[0x5F] = rtc[16] & 0x1;
+
+        let elapsed = unix_time().saturating_sub(u64::from_le_bytes(rtc[..8].try_into().unwrap()));
+        self.advance_minutes(elapsed / 60);
+        self.clock_counter = (elapsed % 60) as u32 * HuC3::CYCLES_PER_SECOND;
+    }
+
+    fn take_save_dirty(&mut self) -> bool {
+        std::mem::replace(&mut self.is_save_dirty, false)
+    }
+
+    fn external_ram(&mut self) -> &mut [u8] {
+        &mut self.external_ram
+    }
+
+    fn bank_writes(&self) -> Vec<(u16, u8)> {
+        vec![
+            (0x2000, self.rom_bank as u8),
+            (0x4000, self.ram_bank as u8),
+            (0x0000, self.mode),
+        ]
+    }
+
+    fn set_infrared_link(&mut self, infrared_link: Box<dyn InfraredLink>) {
+        self.infrared_link = infrared_link;
+        self.infrared_link.set_led(self.led_on);
+    }
+
+    fn speaker_level(&self) -> f32 {
+        if self.tone_cycles == 0 { 0.0 } else if self.speaker_high { 1.0 } else { -1.0 }
+    }
+}
+
+impl Stateful for HuC3 {
+    fn visit_state(&mut self, state: &mut State) {
+        let mut led_on = self.led_on;
+        visit!(state,
+            self.rom_bank, self.ram_bank, self.mode, self.external_ram[..], self.memory, self.address,
+            self.response, self.extended_command, self.minutes, self.days, self.clock_counter,
+            self.tone_cycles, self.tone_counter, self.tone_half_period, self.speaker_high, led_on,
+        );
+        state.check(self.rom_bank <= self.rom_mask);
+        if state.is_loading() && led_on != self.led_on {
+            self.led_on = led_on;
+            self.infrared_link.set_led(led_on);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::super::super::test_rom;
+
+    fn huc3() -> HuC3 {
+        let rom = test_rom::rom(0xFE, 0x02, &[]);
+        HuC3::new(Header::new(&rom).unwrap(), rom)
+    }
+
+    fn commands(huc3: &mut HuC3, commands: &[u8]) {
+        huc3.write(0x0000, 0x0B);
+        for command in commands.iter() { huc3.write(0xA000, *command) }
+        huc3.write(0x0000, 0x0C);
+    }
+
+    #[test]
+    fn reads_nibbles_after_extended_command_2() {
+        let mut huc3 = huc3();
+        commands(&mut huc3, &[0x40, 0x58, 0x35, 0x36, 0x40, 0x58, 0x62]);
+        assert_eq!(huc3.read(0xA000), 0x1);
+
+        commands(&mut huc3, &[0x10]);
+        assert_eq!(huc3.read(0xA000), 0x5);
+        commands(&mut huc3, &[0x10]);
+        assert_eq!(huc3.read(0xA000), 0x6);
+    }
+}
@@ -1,10 +1,13 @@
 use super::MemoryBankController;
 use super::MemoryHandler;
 use super::Header;
+use super::super::super::state::{Stateful, State};
 
 pub struct MBC1 {
     rom_mask: usize,
     ram_mask: usize,
+    // MBC1M multicarts leave bit 4 of the bank register unconnected and wire the upper bits one lower
+    bank_shift: usize,
 
     rom: Vec<u8>,
     rom_bank: usize,
@@ -12,19 +15,20 @@ pub struct MBC1 {
     ram_enable: bool,
     is_ram_banking: bool,
     external_ram: Vec<u8>,
+    is_save_dirty: bool, // Battery backed storage changed since the last `take_save_dirty`
 
     has_ram: bool,
-    _has_battery: bool,
+    has_battery: bool,
 }
 
 impl MBC1 {
     pub fn new(header: Header, rom: Vec<u8>, has_ram: bool, has_battery: bool) -> Self {
         let ram_size = header.get_ram_size();
         let rom_size = header.get_rom_size();
-        assert_eq!(rom_size, rom.len());
         MBC1 {
             rom_mask: rom_size / 0x4000 - 1,
             ram_mask: if ram_size == 0x0800 || ram_size == 0 { 0 } else { ram_size / 0x2000 - 1 },
+            bank_shift: if MBC1::is_multicart(&rom) { 4 } else { 5 },
 
             rom,
             rom_bank: 1,
@@ -32,17 +36,32 @@ impl MBC1 {
             ram_enable: false,
             is_ram_banking: false,
             external_ram: vec![0; ram_size],
+            is_save_dirty: false,
 
             has_ram: has_ram && ram_size > 0,
-            _has_battery: has_battery,
+            has_battery,
         }
     }
+
+    // 1MB compilations repeat the Nintendo logo at the start of every game, each 0x10 banks apart
+    fn is_multicart(rom: &[u8]) -> bool {
+        let logo = &rom[0x0104..0x0134];
+        rom.len() == 0x100000 && [0x10, 0x20, 0x30].iter().any(|bank| &rom[bank * 0x4000 + 0x0104..bank * 0x4000 + 0x0134] == logo)
+    }
+
+    fn low_bank_mask(&self) -> usize {
+        (1 << self.bank_shift) - 1
+    }
+
+    fn high_bank_mask(&self) -> usize {
+        0x3 << self.bank_shift
+    }
 }
 
 impl MemoryHandler for MBC1 {
     fn read(&self, addr: u16) -> u8 {
         match addr & 0xC000 {
-            0x0000 => if self.is_ram_banking { self.rom[(self.rom_bank & 0x60) * 0x4000 + addr as usize] }
+            0x0000 => if self.is_ram_banking { self.rom[(self.rom_bank & self.high_bank_mask()) * 0x4000 + addr as usize] }
                 else { self.rom[addr as usize] },
             0x4000 => self.rom[self.rom_bank * 0x4000 + (addr - 0x4000) as usize],
             0x8000 => if self.ram_enable && self.has_ram {
@@ -58,20 +77,22 @@ impl MemoryHandler for MBC1 {
             0x2000 => {
                 let mut bank = (value & 0x1F) as usize;
                 if bank == 0 { bank = 1; };
-                self.rom_bank = (self.rom_bank & !0x1F | bank) & self.rom_mask;
+                let low_bank_mask = self.low_bank_mask();
+                self.rom_bank = (self.rom_bank & !low_bank_mask | bank & low_bank_mask) & self.rom_mask;
             },
             0x4000 => if self.is_ram_banking && self.has_ram { self.ram_bank = (value as usize & 0x3) & self.ram_mask }
-                      else { self.rom_bank = (self.rom_bank & !0xE0 | (value as usize & 0x3) << 5) & self.rom_mask },
+                      else { self.rom_bank = (self.rom_bank & !self.high_bank_mask() | (value as usize & 0x3) << self.bank_shift) & self.rom_mask },
             0x6000 => {
                 self.is_ram_banking = value & 0x1 != 0;
                 if self.is_ram_banking && self.has_ram {
-                    self.rom_bank = (self.rom_bank & 0x1F) & self.rom_mask;
+                    self.rom_bank = (self.rom_bank & self.low_bank_mask()) & self.rom_mask;
                 } else {
                     self.ram_bank = 0;
                 }
             },
             0xA000 => if self.ram_enable && self.has_ram {
-                self.external_ram[self.ram_bank * 0x2000 + (addr as usize - 0xA000)] = value
+                self.external_ram[self.ram_bank * 0x2000 + (addr as usize - 0xA000)] = value;
+                self.is_save_dirty = true;
             },
             _ => panic!("Shouldn't be here!"),
         }
@@ -79,9 +100,47 @@ impl MemoryHandler for MBC1 {
 }
 
 impl MemoryBankController for MBC1 {
-    fn get_boot_rom_ptr(&mut self) -> *mut [u8; 0x900] {
-        self.rom[..0x900].as_mut_ptr() as *mut [u8; 0x900]
+    fn emulate_clock(&mut self) {}
+
+    fn save_data(&self) -> Option<Vec<u8>> {
+        if self.has_battery { Some(self.external_ram.to_vec()) } else { None }
     }
 
-    fn emulate_clock(&mut self) {}
+    fn load_save_data(&mut self, data: &[u8]) {
+        let len = data.len().min(self.external_ram.len());
+        self.external_ram[..len].copy_from_slice(&data[..len]);
+    }
+
+    fn take_save_dirty(&mut self) -> bool {
+        std::mem::replace(&mut self.is_save_dirty, false)
+    }
+
+    fn external_ram(&mut self) -> &mut [u8] {
+        &mut self.external_ram
+    }
+
+    // The mode goes first as switching it resets the banks
+    fn bank_writes(&self) -> Vec<(u16, u8)> {
+        let upper_bits = if self.is_ram_banking && self.has_ram { self.ram_bank } else { self.rom_bank >> self.bank_shift };
+        // On multicarts low bank 0 is reached by writing 0x10, as writing 0 selects bank 1
+        let low_bank = match self.rom_bank & self.low_bank_mask() {
+            0 if self.bank_shift == 4 => 0x10,
+            low_bank => low_bank,
+        };
+        vec![
+            (0x6000, self.is_ram_banking as u8),
+            (0x0000, if self.ram_enable { 0x0A } else { 0x00 }),
+            (0x2000, low_bank as u8),
+            (0x4000, upper_bits as u8 & 0x3),
+        ]
+    }
+}
+
+impl Stateful for MBC1 {
+    fn visit_state(&mut self, state: &mut State) {
+        visit!(state,
+            self.rom_bank, self.ram_bank, self.ram_enable, self.is_ram_banking, self.external_ram[..],
+        );
+        state.check(self.rom_bank <= self.rom_mask && self.ram_bank <= self.ram_mask);
+    }
 }
@@ -1,6 +1,7 @@
 use super::MemoryBankController;
 use super::MemoryHandler;
 use super::Header;
+use super::super::super::state::{Stateful, State};
 
 pub struct MBC2 {
     rom_mask: usize,
@@ -9,14 +10,14 @@ pub struct MBC2 {
     rom_bank: usize,
     ram_enable: bool,
     ram: [u8; 0x200],
+    is_save_dirty: bool, // Battery backed storage changed since the last `take_save_dirty`
 
-    _has_battery: bool,
+    has_battery: bool,
 }
 
 impl MBC2 {
     pub fn new(header: Header, rom: Vec<u8>, has_battery: bool) -> Self {
         let rom_size = header.get_rom_size();
-        assert_eq!(rom_size, rom.len());
         MBC2 {
             rom_mask: rom_size / 0x4000 - 1,
 
@@ -24,8 +25,9 @@ impl MBC2 {
             rom_bank: 1,
             ram_enable: false,
             ram: [0; 0x200],
+            is_save_dirty: false,
 
-            _has_battery: has_battery,
+            has_battery,
         }
     }
 }
@@ -43,6 +45,7 @@ impl MemoryHandler for MBC2 {
         }
     }
     
+    #[allow(clippy::collapsible_if, clippy::manual_range_contains)]
     fn write(&mut self, addr: u16, value: u8) {
         if addr < 0x4000 {
             if addr & 0x0100 == 0 {
@@ -56,15 +59,42 @@ impl MemoryHandler for MBC2 {
         } else if addr >= 0xA000 && addr < 0xA200 {
             if self.ram_enable {
                 self.ram[addr as usize - 0xA000] = value & 0x0F;
+                self.is_save_dirty = true;
             }
         }
     }
 }
 
 impl MemoryBankController for MBC2 {
-    fn get_boot_rom_ptr(&mut self) -> *mut [u8; 0x900] {
-        self.rom[..0x900].as_mut_ptr() as *mut [u8; 0x900]
+    fn emulate_clock(&mut self) {}
+
+    fn save_data(&self) -> Option<Vec<u8>> {
+        if self.has_battery { Some(self.ram.to_vec()) } else { None }
     }
 
-    fn emulate_clock(&mut self) {}
+    fn load_save_data(&mut self, data: &[u8]) {
+        let len = data.len().min(self.ram.len());
+        self.ram[..len].copy_from_slice(&data[..len]);
+    }
+
+    fn take_save_dirty(&mut self) -> bool {
+        std::mem::replace(&mut self.is_save_dirty, false)
+    }
+
+    fn external_ram(&mut self) -> &mut [u8] {
+        &mut self.ram
+    }
+
+    fn bank_writes(&self) -> Vec<(u16, u8)> {
+        vec![(0x0000, if self.ram_enable { 0x0A } else { 0x00 }), (0x0100, self.rom_bank as u8)]
+    }
+}
+
+impl Stateful for MBC2 {
+    fn visit_state(&mut self, state: &mut State) {
+        visit!(state,
+            self.rom_bank, self.ram_enable, self.ram,
+        );
+        state.check(self.rom_bank <= self.rom_mask);
+    }
 }
@@ -1,6 +1,10 @@
 use super::MemoryBankController;
 use super::MemoryHandler;
 use super::Header;
+use super::unix_time;
+use super::super::super::state::{Stateful, State};
+
+use std::convert::TryInto;
 
 pub struct MBC3 {
     rom_mask: usize,
@@ -11,6 +15,7 @@ pub struct MBC3 {
     ram_bank: usize, // RAM Bank or RTC Register depending on value
     ram_enable: bool, // RAM and RTC Registers Enable
     external_ram: Vec<u8>,
+    is_save_dirty: bool, // Battery backed storage changed since the last `take_save_dirty`
     rtc_registers: [u8; 5],
     latched_rtc_registers: [u8; 5],
     latch_clock_data: bool,
@@ -21,14 +26,13 @@ pub struct MBC3 {
 
     has_timer: bool,
     has_ram: bool,
-    _has_battery: bool,
+    has_battery: bool,
 }
 
 impl MBC3 {
     pub fn new(header: Header, rom: Vec<u8>, has_timer: bool, has_ram: bool, has_battery: bool) -> Self {
         let ram_size = header.get_ram_size();
         let rom_size = header.get_rom_size();
-        assert_eq!(rom_size, rom.len());
         MBC3 {
             rom_mask: rom_size / 0x4000 - 1,
             ram_mask: if ram_size == 0x0800 || ram_size == 0 { 0 } else { ram_size / 0x2000 - 1 },
@@ -38,6 +42,7 @@ impl MBC3 {
             ram_bank: 0,
             ram_enable: false,
             external_ram: vec![0xFF; ram_size],
+            is_save_dirty: false,
             rtc_registers: [0; 5],
             latched_rtc_registers: [0; 5],
             latch_clock_data: false,
@@ -48,11 +53,27 @@ impl MBC3 {
 
             has_timer,
             has_ram: has_ram && ram_size > 0,
-            _has_battery: has_battery,
+            has_battery,
         }
     }
 }
 
+impl MBC3 {
+    // Catches the clock up on time that passed while the emulator wasn't running
+    fn advance_rtc(&mut self, seconds: u64) {
+        let regs = &mut self.rtc_registers;
+        let days = ((regs[4] as u64 & 0x1) << 8) | regs[3] as u64;
+        let total = seconds + regs[0] as u64 + regs[1] as u64 * 60 + regs[2] as u64 * 3600 + days * 86400;
+        let days = total / 86400;
+        regs[0] = (total % 60) as u8;
+        regs[1] = (total / 60 % 60) as u8;
+        regs[2] = (total / 3600 % 24) as u8;
+        regs[3] = days as u8;
+        regs[4] = regs[4] & !0x1 | (days >> 8) as u8 & 0x1;
+        if days > 0x1FF { regs[4] |= 0x80; }
+    }
+}
+
 impl MemoryHandler for MBC3 {
     fn read(&self, addr: u16) -> u8 {
         match addr & 0xC000 {
@@ -90,10 +111,12 @@ impl MemoryHandler for MBC3 {
             0xA000 => if self.ram_enable {
                 if self.ram_bank <= 0x3 && self.has_ram {
                     self.external_ram[(self.ram_bank & self.ram_mask) * 0x2000 + (addr as usize - 0xA000)] = value;
+                    self.is_save_dirty = true;
                 } else if self.ram_bank >= 0x8 && self.ram_bank <= 0xC && self.has_timer {
                     self.rtc_registers[self.ram_bank - 0x8] = value;
 
                     self.halt_timer = self.rtc_registers[4] & 0x40 != 0;
+                    self.is_save_dirty = true;
                 }
             },
             _ => panic!("Shouldn't be here!"),
@@ -102,10 +125,6 @@ impl MemoryHandler for MBC3 {
 }
 
 impl MemoryBankController for MBC3 {
-    fn get_boot_rom_ptr(&mut self) -> *mut [u8; 0x900] {
-        self.rom[..0x900].as_mut_ptr() as *mut [u8; 0x900]
-    }
-
     fn emulate_clock(&mut self) {
         if !self.has_timer || self.halt_timer { return }
 
@@ -135,4 +154,72 @@ impl MemoryBankController for MBC3 {
             self.clock_counter = 0;
         } else { self.clock_counter += 1; }
     }
+
+    // The RTC is appended to the RAM
+    fn save_data(&self) -> Option<Vec<u8>> {
+        if !self.has_battery { return None }
+        let mut data = self.external_ram.clone();
+        data.extend(self.rtc_data().unwrap_or_default());
+        Some(data)
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) {
+        let ram_len = data.len().min(self.external_ram.len());
+        self.external_ram[..ram_len].copy_from_slice(&data[..ram_len]);
+        if ram_len == self.external_ram.len() {
+            self.load_rtc_data(&data[ram_len..]);
+        }
+    }
+
+    fn take_save_dirty(&mut self) -> bool {
+        std::mem::replace(&mut self.is_save_dirty, false)
+    }
+
+    fn external_ram(&mut self) -> &mut [u8] {
+        &mut self.external_ram
+    }
+
+    fn bank_writes(&self) -> Vec<(u16, u8)> {
+        vec![(0x0000, if self.ram_enable { 0x0A } else { 0x00 }), (0x2000, self.rom_bank as u8), (0x4000, self.ram_bank as u8)]
+    }
+
+    // The VBA/BGB footer layout: the current and latched registers as 32 bit little endian values,
+    // followed by the 64 bit UNIX timestamp of the save
+    fn rtc_data(&self) -> Option<Vec<u8>> {
+        if !self.has_timer { return None }
+        let mut data = Vec::new();
+        for reg in self.rtc_registers.iter().chain(self.latched_rtc_registers.iter()) {
+            data.extend_from_slice(&(*reg as u32).to_le_bytes());
+        }
+        data.extend_from_slice(&unix_time().to_le_bytes());
+        Some(data)
+    }
+
+    fn load_rtc_data(&mut self, data: &[u8]) {
+        // Some emulators write a 44 byte footer with a 32 bit timestamp
+        if !self.has_timer || (data.len() != 44 && data.len() != 48) { return }
+        let reg = |i: usize| u32::from_le_bytes(data[i * 4..i * 4 + 4].try_into().unwrap()) as u8;
+        for i in 0..5 {
+            self.rtc_registers[i] = reg(i);
+            self.latched_rtc_registers[i] = reg(i + 5);
+        }
+        self.halt_timer = self.rtc_registers[4] & 0x40 != 0;
+        let timestamp = if data.len() == 48 {
+            u64::from_le_bytes(data[40..48].try_into().unwrap())
+        } else { u32::from_le_bytes(data[40..44].try_into().unwrap()) as u64 };
+        if !self.halt_timer {
+            self.advance_rtc(unix_time().saturating_sub(timestamp));
+        }
+    }
+}
+
+impl Stateful for MBC3 {
+    fn visit_state(&mut self, state: &mut State) {
+        visit!(state,
+            self.rom_bank, self.ram_bank, self.ram_enable, self.external_ram[..], self.rtc_registers,
+            self.latched_rtc_registers, self.latch_clock_data, self.halt_timer, self.clock_counter,
+            self.rtc_counter,
+        );
+        state.check(self.rom_bank <= self.rom_mask);
+    }
 }
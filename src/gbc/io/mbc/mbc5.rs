@@ -0,0 +1,139 @@
+use super::MemoryBankController;
+use super::MemoryHandler;
+use super::Header;
+use super::super::super::state::{Stateful, State};
+
+pub struct MBC5 {
+    rom_mask: usize,
+    ram_mask: usize,
+
+    rom: Vec<u8>,
+    rom_bank: usize, // 9 bits, bank 0 can be mapped to 0x4000-0x7FFF
+    ram_bank: usize,
+    ram_enable: bool,
+    external_ram: Vec<u8>,
+    is_save_dirty: bool, // Battery backed storage changed since the last `take_save_dirty`
+
+    has_ram: bool,
+    has_battery: bool,
+
+    // Rumble cartridges use bit 3 of the RAM bank register to drive the motor
+    has_rumble: bool,
+    motor_on: bool,
+    rumble_callback: Option<Box<dyn FnMut(bool)>>,
+}
+
+impl MBC5 {
+    pub fn new(header: Header, rom: Vec<u8>, has_ram: bool, has_battery: bool, has_rumble: bool) -> Self {
+        let ram_size = header.get_ram_size();
+        let rom_size = header.get_rom_size();
+        MBC5 {
+            rom_mask: rom_size / 0x4000 - 1,
+            ram_mask: if ram_size <= 0x2000 { 0 } else { ram_size / 0x2000 - 1 },
+
+            rom,
+            rom_bank: 1,
+            ram_bank: 0,
+            ram_enable: false,
+            external_ram: vec![0; ram_size],
+            is_save_dirty: false,
+
+            has_ram: has_ram && ram_size > 0,
+            has_battery,
+
+            has_rumble,
+            motor_on: false,
+            rumble_callback: None,
+        }
+    }
+
+    fn set_motor(&mut self, motor_on: bool) {
+        if motor_on == self.motor_on { return }
+        self.motor_on = motor_on;
+        if let Some(rumble_callback) = &mut self.rumble_callback {
+            rumble_callback(motor_on);
+        }
+    }
+
+    fn ram_addr(&self, addr: u16) -> usize {
+        (self.ram_bank * 0x2000 + (addr as usize - 0xA000)) % self.external_ram.len()
+    }
+}
+
+impl MemoryHandler for MBC5 {
+    fn read(&self, addr: u16) -> u8 {
+        match addr & 0xC000 {
+            0x0000 => self.rom[addr as usize],
+            0x4000 => self.rom[(self.rom_bank & self.rom_mask) * 0x4000 + (addr - 0x4000) as usize],
+            0x8000 => if self.ram_enable && self.has_ram {
+                self.external_ram[self.ram_addr(addr)]
+            } else { 0xFF },
+            _ => panic!("Shouldn't be here!"),
+        }
+    }
+
+    fn write(&mut self, addr: u16, value: u8) {
+        match addr & 0xF000 {
+            0x0000 | 0x1000 => self.ram_enable = value & 0x0F == 0x0A,
+            0x2000 => self.rom_bank = self.rom_bank & 0x100 | value as usize,
+            0x3000 => self.rom_bank = self.rom_bank & 0xFF | (value as usize & 0x1) << 8,
+            0x4000 | 0x5000 => if self.has_rumble {
+                self.ram_bank = (value as usize & 0x7) & self.ram_mask;
+                self.set_motor(value & 0x8 != 0);
+            } else {
+                self.ram_bank = (value as usize & 0xF) & self.ram_mask;
+            },
+            0x6000 | 0x7000 => {},
+            0xA000 | 0xB000 => if self.ram_enable && self.has_ram {
+                let addr = self.ram_addr(addr);
+                self.external_ram[addr] = value;
+                self.is_save_dirty = true;
+            },
+            _ => panic!("Shouldn't be here!"),
+        }
+    }
+}
+
+impl MemoryBankController for MBC5 {
+    fn emulate_clock(&mut self) {}
+
+    fn save_data(&self) -> Option<Vec<u8>> {
+        if self.has_battery { Some(self.external_ram.to_vec()) } else { None }
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) {
+        let len = data.len().min(self.external_ram.len());
+        self.external_ram[..len].copy_from_slice(&data[..len]);
+    }
+
+    fn take_save_dirty(&mut self) -> bool {
+        std::mem::replace(&mut self.is_save_dirty, false)
+    }
+
+    fn external_ram(&mut self) -> &mut [u8] {
+        &mut self.external_ram
+    }
+
+    fn bank_writes(&self) -> Vec<(u16, u8)> {
+        vec![
+            (0x0000, if self.ram_enable { 0x0A } else { 0x00 }),
+            (0x2000, self.rom_bank as u8),
+            (0x3000, (self.rom_bank >> 8) as u8),
+            (0x4000, self.ram_bank as u8 | if self.motor_on { 0x8 } else { 0 }),
+        ]
+    }
+
+    fn set_rumble_callback(&mut self, rumble_callback: Box<dyn FnMut(bool)>) {
+        self.rumble_callback = Some(rumble_callback);
+    }
+}
+
+impl Stateful for MBC5 {
+    fn visit_state(&mut self, state: &mut State) {
+        let mut motor_on = self.motor_on;
+        visit!(state, self.rom_bank, self.ram_bank, self.ram_enable, motor_on, self.external_ram[..]);
+        state.check(self.rom_bank <= 0x1FF && self.ram_bank <= self.ram_mask);
+        // The frontend hears about the motor changing state the same way as during emulation
+        if state.is_loading() { self.set_motor(motor_on) }
+    }
+}
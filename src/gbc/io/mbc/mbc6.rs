@@ -0,0 +1,315 @@
+use super::MemoryBankController;
+use super::MemoryHandler;
+use super::Header;
+use super::super::super::state::{Stateful, State};
+
+// Mapper with 1MB of flash next to the ROM, split into two independently switched 8KB windows at 0x4000 (A)
+// and 0x6000 (B) that each map a bank of either ROM or flash, and two 4KB RAM windows at 0xA000 and 0xB000.
+// The registers are at 0x0000 (RAM enable), 0x0400/0x0800 (RAM bank A/B), 0x0C00 (flash enable),
+// 0x1000 (flash write enable), 0x2000/0x3000 (bank A/B) and 0x2800/0x3800 (0x08 maps flash to window A/B).
+//
+// Flash takes JEDEC commands unlocked by writing 0xAA to 0x5555 and 0x55 to 0x2AAA of the chip: 0x90 shows
+// the chip ID, 0xA0 programs the next byte written, 0x80 followed by another unlock and 0x10 or 0x30 erases
+// the chip or the sector written to, and 0xF0 returns to reading.
+pub struct MBC6 {
+    rom_mask: usize,
+    ram_mask: usize,
+
+    rom: Vec<u8>,
+    rom_banks: [usize; 2],
+    is_flash_mapped: [bool; 2],
+    ram_banks: [usize; 2],
+    ram_enable: bool,
+    external_ram: Vec<u8>,
+
+    flash: Vec<u8>,
+    flash_enable: bool,
+    flash_write_enable: bool,
+    flash_state: u8,
+    is_id_mode: bool,
+    is_save_dirty: bool, // RAM or flash changed since the last `take_save_dirty`
+}
+
+impl MBC6 {
+    pub fn new(header: Header, rom: Vec<u8>) -> Self {
+        let ram_size = header.get_ram_size();
+        let rom_size = header.get_rom_size();
+        MBC6 {
+            rom_mask: rom_size / 0x2000 - 1,
+            ram_mask: if ram_size <= 0x1000 { 0 } else { ram_size / 0x1000 - 1 },
+
+            rom,
+            rom_banks: [2, 3],
+            is_flash_mapped: [false; 2],
+            ram_banks: [0, 1],
+            ram_enable: false,
+            external_ram: vec![0; ram_size],
+
+            flash: vec![0xFF; MBC6::FLASH_SIZE],
+            flash_enable: false,
+            flash_write_enable: false,
+            flash_state: MBC6::FLASH_READY,
+            is_id_mode: false,
+            is_save_dirty: false,
+        }
+    }
+
+    // MX29F008, erased sectors are treated as evenly sized
+    const FLASH_SIZE: usize = 0x100000;
+    const FLASH_SECTOR_SIZE: usize = 0x20000;
+    const FLASH_ID: [u8; 2] = [0xC2, 0x81];
+
+    // Steps of the command sequences
+    const FLASH_READY: u8 = 0;
+    const FLASH_UNLOCK1: u8 = 1;
+    const FLASH_UNLOCK2: u8 = 2;
+    const FLASH_PROGRAM: u8 = 3;
+    const FLASH_ERASE: u8 = 4;
+    const FLASH_ERASE_UNLOCK1: u8 = 5;
+    const FLASH_ERASE_UNLOCK2: u8 = 6;
+
+    // Window and address within the ROM or flash of an address in 0x4000-0x7FFF
+    fn window_addr(&self, addr: u16) -> (usize, usize) {
+        let window = (addr as usize - 0x4000) / 0x2000;
+        let bank = if self.is_flash_mapped[window] {
+            self.rom_banks[window] & (MBC6::FLASH_SIZE / 0x2000 - 1)
+        } else {
+            self.rom_banks[window] & self.rom_mask
+        };
+        (window, bank * 0x2000 + (addr as usize & 0x1FFF))
+    }
+
+    fn ram_addr(&self, addr: u16) -> Option<usize> {
+        if self.external_ram.is_empty() { return None }
+        let window = (addr as usize - 0xA000) / 0x1000;
+        Some(((self.ram_banks[window] & self.ram_mask) * 0x1000 + (addr as usize & 0x0FFF)) % self.external_ram.len())
+    }
+
+    fn write_flash(&mut self, flash_addr: usize, value: u8) {
+        // Only the low 15 address bits are decoded for the unlock sequences
+        let command_addr = flash_addr & 0x7FFF;
+        self.flash_state = match (self.flash_state, command_addr, value) {
+            (MBC6::FLASH_PROGRAM, _, _) => {
+                // Programming can only clear bits, setting them takes an erase. The byte is data even when it's 0xF0.
+                self.flash[flash_addr] &= value;
+                self.is_save_dirty = true;
+                MBC6::FLASH_READY
+            },
+            (_, _, 0xF0) => {
+                self.is_id_mode = false;
+                MBC6::FLASH_READY
+            },
+            (MBC6::FLASH_READY, 0x5555, 0xAA) => MBC6::FLASH_UNLOCK1,
+            (MBC6::FLASH_UNLOCK1, 0x2AAA, 0x55) => MBC6::FLASH_UNLOCK2,
+            (MBC6::FLASH_UNLOCK2, 0x5555, 0x90) => {
+                self.is_id_mode = true;
+                MBC6::FLASH_READY
+            },
+            (MBC6::FLASH_UNLOCK2, 0x5555, 0xA0) => MBC6::FLASH_PROGRAM,
+            (MBC6::FLASH_UNLOCK2, 0x5555, 0x80) => MBC6::FLASH_ERASE,
+            (MBC6::FLASH_ERASE, 0x5555, 0xAA) => MBC6::FLASH_ERASE_UNLOCK1,
+            (MBC6::FLASH_ERASE_UNLOCK1, 0x2AAA, 0x55) => MBC6::FLASH_ERASE_UNLOCK2,
+            (MBC6::FLASH_ERASE_UNLOCK2, 0x5555, 0x10) => {
+                self.flash.iter_mut().for_each(|byte| *byte = 0xFF);
+                self.is_save_dirty = true;
+                MBC6::FLASH_READY
+            },
+            (MBC6::FLASH_ERASE_UNLOCK2, _, 0x30) => {
+                let sector = flash_addr / MBC6::FLASH_SECTOR_SIZE * MBC6::FLASH_SECTOR_SIZE;
+                self.flash[sector..sector + MBC6::FLASH_SECTOR_SIZE].iter_mut().for_each(|byte| *byte = 0xFF);
+                self.is_save_dirty = true;
+                MBC6::FLASH_READY
+            },
+            _ => MBC6::FLASH_READY,
+        };
+    }
+}
+
+impl MemoryHandler for MBC6 {
+    fn read(&self, addr: u16) -> u8 {
+        match addr & 0xC000 {
+            0x0000 => self.rom[addr as usize],
+            0x4000 => {
+                let (window, addr) = self.window_addr(addr);
+                if !self.is_flash_mapped[window] {
+                    self.rom[addr]
+                } else if !self.flash_enable {
+                    0xFF
+                } else if self.is_id_mode {
+                    MBC6::FLASH_ID[addr & 0x1]
+                } else {
+                    self.flash[addr]
+                }
+            },
+            0x8000 => if self.ram_enable {
+                self.ram_addr(addr).map_or(0xFF, |addr| self.external_ram[addr])
+            } else { 0xFF },
+            _ => panic!("Shouldn't be here!"),
+        }
+    }
+
+    fn write(&mut self, addr: u16, value: u8) {
+        match addr {
+            0x0000..=0x03FF => self.ram_enable = value & 0x0F == 0x0A,
+            0x0400..=0x07FF => self.ram_banks[0] = value as usize & 0x7,
+            0x0800..=0x0BFF => self.ram_banks[1] = value as usize & 0x7,
+            0x0C00..=0x0FFF => self.flash_enable = value & 0x01 != 0,
+            0x1000..=0x1FFF => self.flash_write_enable = value & 0x01 != 0,
+            0x2000..=0x27FF => self.rom_banks[0] = value as usize & 0x7F,
+            0x2800..=0x2FFF => self.is_flash_mapped[0] = value == 0x08,
+            0x3000..=0x37FF => self.rom_banks[1] = value as usize & 0x7F,
+            0x3800..=0x3FFF => self.is_flash_mapped[1] = value == 0x08,
+            0x4000..=0x7FFF => {
+                let (window, addr) = self.window_addr(addr);
+                if self.is_flash_mapped[window] && self.flash_enable && self.flash_write_enable {
+                    self.write_flash(addr, value);
+                }
+            },
+            0xA000..=0xBFFF => if self.ram_enable {
+                if let Some(addr) = self.ram_addr(addr) {
+                    self.external_ram[addr] = value;
+                    self.is_save_dirty = true;
+                }
+            },
+            _ => panic!("Shouldn't be here!"),
+        }
+    }
+}
+
+impl MemoryBankController for MBC6 {
+    fn emulate_clock(&mut self) {}
+
+    // RAM followed by the flash
+    fn save_data(&self) -> Option<Vec<u8>> {
+        let mut data = self.external_ram.to_vec();
+        data.extend_from_slice(&self.flash);
+        Some(data)
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) {
+        let ram_len = data.len().min(self.external_ram.len());
+        self.external_ram[..ram_len].copy_from_slice(&data[..ram_len]);
+
+        let flash = &data[ram_len..];
+        let flash_len = flash.len().min(self.flash.len());
+        self.flash[..flash_len].copy_from_slice(&flash[..flash_len]);
+    }
+
+    fn take_save_dirty(&mut self) -> bool {
+        std::mem::replace(&mut self.is_save_dirty, false)
+    }
+
+    fn external_ram(&mut self) -> &mut [u8] {
+        &mut self.external_ram
+    }
+
+    fn bank_writes(&self) -> Vec<(u16, u8)> {
+        let flash_select = |is_flash_mapped: bool| if is_flash_mapped { 0x08 } else { 0x00 };
+        vec![
+            (0x0000, if self.ram_enable { 0x0A } else { 0x00 }),
+            (0x0400, self.ram_banks[0] as u8),
+            (0x0800, self.ram_banks[1] as u8),
+            (0x0C00, self.flash_enable as u8),
+            (0x1000, self.flash_write_enable as u8),
+            (0x2000, self.rom_banks[0] as u8),
+            (0x2800, flash_select(self.is_flash_mapped[0])),
+            (0x3000, self.rom_banks[1] as u8),
+            (0x3800, flash_select(self.is_flash_mapped[1])),
+        ]
+    }
+}
+
+impl Stateful for MBC6 {
+    fn visit_state(&mut self, state: &mut State) {
+        visit!(state,
+            self.rom_banks, self.is_flash_mapped, self.ram_banks, self.ram_enable, self.external_ram[..],
+            self.flash[..], self.flash_enable, self.flash_write_enable, self.flash_state, self.is_id_mode,
+        );
+        state.check(self.rom_banks.iter().all(|bank| *bank <= 0x7F) && self.ram_banks.iter().all(|bank| *bank <= 0x7));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::super::super::test_rom;
+
+    // MBC6 with flash mapped to window A and writable
+    fn mbc6() -> MBC6 {
+        let rom = test_rom::rom(0x20, 0x02, &[]);
+        let mut mbc6 = MBC6::new(Header::new(&rom).unwrap(), rom);
+        for (addr, value) in [(0x0C00, 0x01), (0x1000, 0x01), (0x2800, 0x08)] { mbc6.write(addr, value) }
+        mbc6
+    }
+
+    // Writes `value` to a flash address through window A
+    fn write_flash(mbc6: &mut MBC6, flash_addr: usize, value: u8) {
+        mbc6.write(0x2000, (flash_addr / 0x2000) as u8);
+        mbc6.write(0x4000 + (flash_addr & 0x1FFF) as u16, value);
+    }
+
+    fn read_flash(mbc6: &mut MBC6, flash_addr: usize) -> u8 {
+        mbc6.write(0x2000, (flash_addr / 0x2000) as u8);
+        mbc6.read(0x4000 + (flash_addr & 0x1FFF) as u16)
+    }
+
+    fn unlock(mbc6: &mut MBC6) {
+        write_flash(mbc6, 0x5555, 0xAA);
+        write_flash(mbc6, 0x2AAA, 0x55);
+    }
+
+    fn program(mbc6: &mut MBC6, flash_addr: usize, value: u8) {
+        unlock(mbc6);
+        write_flash(mbc6, 0x5555, 0xA0);
+        write_flash(mbc6, flash_addr, value);
+    }
+
+    #[test]
+    fn program_clears_bits() {
+        let mut mbc6 = mbc6();
+        program(&mut mbc6, 0x12345, 0x5A);
+        assert_eq!(read_flash(&mut mbc6, 0x12345), 0x5A);
+        program(&mut mbc6, 0x12345, 0xF0);
+        assert_eq!(read_flash(&mut mbc6, 0x12345), 0x50);
+
+        // Without the command sequence writes are ignored
+        write_flash(&mut mbc6, 0x12346, 0x00);
+        assert_eq!(read_flash(&mut mbc6, 0x12346), 0xFF);
+    }
+
+    #[test]
+    fn sector_erase_only_erases_its_sector() {
+        let mut mbc6 = mbc6();
+        program(&mut mbc6, 0x20000, 0x00);
+        program(&mut mbc6, 0x3FFFF, 0x00);
+        program(&mut mbc6, 0x40000, 0x00);
+
+        unlock(&mut mbc6);
+        write_flash(&mut mbc6, 0x5555, 0x80);
+        unlock(&mut mbc6);
+        write_flash(&mut mbc6, 0x30000, 0x30);
+        assert_eq!(read_flash(&mut mbc6, 0x20000), 0xFF);
+        assert_eq!(read_flash(&mut mbc6, 0x3FFFF), 0xFF);
+        assert_eq!(read_flash(&mut mbc6, 0x40000), 0x00);
+    }
+
+    #[test]
+    fn reports_flash_and_ram_changes() {
+        let mut mbc6 = mbc6();
+        assert!(!mbc6.take_save_dirty());
+        unlock(&mut mbc6);
+        write_flash(&mut mbc6, 0x5555, 0x90);
+        write_flash(&mut mbc6, 0x0000, 0xF0);
+        assert!(!mbc6.take_save_dirty());
+
+        program(&mut mbc6, 0x0000, 0x00);
+        assert!(mbc6.take_save_dirty());
+        assert!(!mbc6.take_save_dirty());
+
+        mbc6.write(0xA000, 0x12);
+        assert!(!mbc6.take_save_dirty());
+        mbc6.write(0x0000, 0x0A);
+        mbc6.write(0xA000, 0x12);
+        assert!(mbc6.take_save_dirty());
+    }
+}
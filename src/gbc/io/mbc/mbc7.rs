@@ -0,0 +1,305 @@
+use super::MemoryBankController;
+use super::MemoryHandler;
+use super::Header;
+use super::super::super::state::{Stateful, State};
+
+// Mapper with a two-axis accelerometer and a 93LC56 serial EEPROM instead of RAM. Both are only mapped
+// to 0xA000-0xAFFF after writing 0x0A to 0x0000-0x1FFF and 0x40 to 0x4000-0x5FFF. Bits 4-7 of the address
+// select the register: 0 and 1 latch the accelerometer by writing 0x55 then 0xAA, 2-5 read the latched X
+// and Y values and 8 drives the EEPROM pins.
+pub struct MBC7 {
+    rom_mask: usize,
+
+    rom: Vec<u8>,
+    rom_bank: usize,
+    ram_enable: bool,
+    ram_enable2: bool,
+
+    // Accelerometer, in g
+    tilt_x: f32,
+    tilt_y: f32,
+    latched_x: u16,
+    latched_y: u16,
+    is_latch_erased: bool,
+
+    eeprom: EEPROM,
+}
+
+impl MBC7 {
+    pub fn new(header: Header, rom: Vec<u8>) -> Self {
+        let rom_size = header.get_rom_size();
+        MBC7 {
+            rom_mask: rom_size / 0x4000 - 1,
+
+            rom,
+            rom_bank: 1,
+            ram_enable: false,
+            ram_enable2: false,
+
+            tilt_x: 0.0,
+            tilt_y: 0.0,
+            latched_x: 0x8000,
+            latched_y: 0x8000,
+            is_latch_erased: false,
+
+            eeprom: EEPROM::new(),
+        }
+    }
+
+    // Level reads 0x81D0 on both axes, and each g of tilt moves it by about 0x70
+    const ACCELEROMETER_CENTER: f32 = 0x81D0 as f32;
+    const ACCELEROMETER_PER_G: f32 = 0x70 as f32;
+
+    fn accelerometer_value(tilt: f32) -> u16 {
+        (MBC7::ACCELEROMETER_CENTER + tilt.clamp(-2.0, 2.0) * MBC7::ACCELEROMETER_PER_G) as u16
+    }
+}
+
+impl MemoryHandler for MBC7 {
+    fn read(&self, addr: u16) -> u8 {
+        match addr & 0xC000 {
+            0x0000 => self.rom[addr as usize],
+            0x4000 => self.rom[self.rom_bank * 0x4000 + (addr - 0x4000) as usize],
+            0x8000 => if self.ram_enable && self.ram_enable2 && addr < 0xB000 {
+                match (addr >> 4) & 0xF {
+                    0x2 => self.latched_x as u8,
+                    0x3 => (self.latched_x >> 8) as u8,
+                    0x4 => self.latched_y as u8,
+                    0x5 => (self.latched_y >> 8) as u8,
+                    0x6 => 0x00,
+                    0x8 => self.eeprom.read(),
+                    _ => 0xFF,
+                }
+            } else { 0xFF },
+            _ => panic!("Shouldn't be here!"),
+        }
+    }
+
+    fn write(&mut self, addr: u16, value: u8) {
+        match addr & 0xE000 {
+            0x0000 => {
+                self.ram_enable = value & 0x0F == 0x0A;
+                if !self.ram_enable { self.ram_enable2 = false }
+            },
+            0x2000 => self.rom_bank = (value as usize & 0x7F) & self.rom_mask,
+            0x4000 => self.ram_enable2 = self.ram_enable && value == 0x40,
+            0x6000 => {},
+            0xA000 => if self.ram_enable && self.ram_enable2 && addr < 0xB000 {
+                match (addr >> 4) & 0xF {
+                    0x0 if value == 0x55 => {
+                        self.is_latch_erased = true;
+                        self.latched_x = 0x8000;
+                        self.latched_y = 0x8000;
+                    },
+                    0x1 if value == 0xAA && self.is_latch_erased => {
+                        self.is_latch_erased = false;
+                        self.latched_x = MBC7::accelerometer_value(self.tilt_x);
+                        self.latched_y = MBC7::accelerometer_value(self.tilt_y);
+                    },
+                    0x8 => self.eeprom.write(value),
+                    _ => {},
+                }
+            },
+            _ => panic!("Shouldn't be here!"),
+        }
+    }
+}
+
+impl MemoryBankController for MBC7 {
+    fn emulate_clock(&mut self) {}
+
+    fn save_data(&self) -> Option<Vec<u8>> {
+        Some(self.eeprom.data.to_vec())
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) {
+        let len = data.len().min(self.eeprom.data.len());
+        self.eeprom.data[..len].copy_from_slice(&data[..len]);
+    }
+
+    fn take_save_dirty(&mut self) -> bool {
+        std::mem::replace(&mut self.eeprom.is_dirty, false)
+    }
+
+    fn external_ram(&mut self) -> &mut [u8] {
+        &mut self.eeprom.data
+    }
+
+    fn bank_writes(&self) -> Vec<(u16, u8)> {
+        vec![
+            (0x0000, if self.ram_enable { 0x0A } else { 0x00 }),
+            (0x2000, self.rom_bank as u8),
+            (0x4000, if self.ram_enable2 { 0x40 } else { 0x00 }),
+        ]
+    }
+
+    fn set_tilt(&mut self, x: f32, y: f32) {
+        self.tilt_x = x;
+        self.tilt_y = y;
+    }
+}
+
+impl Stateful for MBC7 {
+    fn visit_state(&mut self, state: &mut State) {
+        visit!(state,
+            self.rom_bank, self.ram_enable, self.ram_enable2, self.latched_x, self.latched_y,
+            self.is_latch_erased,
+        );
+        state.check(self.rom_bank <= self.rom_mask);
+        self.eeprom.visit_state(state);
+    }
+}
+
+// 93LC56 in 16 bit mode: 128 words, stored little endian. While chip select is high, bits are shifted in
+// on rising clock edges: a start bit, a 2 bit opcode and 8 address bits, followed by 16 data bits for writes.
+#[allow(clippy::upper_case_acronyms)]
+struct EEPROM {
+    data: [u8; 0x100],
+    chip_select: bool,
+    clock: bool,
+    data_in: bool,
+    data_out: bool,
+
+    state: u8,
+    shift: u32, // Bits shifted in, or out while reading
+    bit_count: u32,
+    address: usize,
+    is_write_enabled: bool,
+    is_dirty: bool, // Written since the last `take_save_dirty`
+}
+
+impl EEPROM {
+    fn new() -> Self {
+        EEPROM {
+            data: [0xFF; 0x100],
+            chip_select: false,
+            clock: false,
+            data_in: false,
+            data_out: true,
+
+            state: EEPROM::IDLE,
+            shift: 0,
+            bit_count: 0,
+            address: 0,
+            is_write_enabled: false,
+            is_dirty: false,
+        }
+    }
+
+    const IDLE: u8 = 0;
+    const COMMAND: u8 = 1;
+    const READ: u8 = 2;
+    const WRITE: u8 = 3;
+    const WRITE_ALL: u8 = 4;
+
+    fn read(&self) -> u8 {
+        (self.chip_select as u8) << 7 | (self.clock as u8) << 6 | (self.data_in as u8) << 1 | self.data_out as u8
+    }
+
+    fn write(&mut self, value: u8) {
+        let chip_select = value & 0x80 != 0;
+        let clock = value & 0x40 != 0;
+        self.data_in = value & 0x02 != 0;
+
+        if !chip_select {
+            // Deselecting aborts any command, the chip reports ready as writes complete instantly
+            self.state = EEPROM::IDLE;
+            self.data_out = true;
+        } else if clock && !self.clock {
+            self.clock_in();
+        }
+        self.chip_select = chip_select;
+        self.clock = clock;
+    }
+
+    fn clock_in(&mut self) {
+        let bit = self.data_in as u32;
+        match self.state {
+            EEPROM::IDLE if bit == 1 => {
+                self.state = EEPROM::COMMAND;
+                self.shift = 0;
+                self.bit_count = 0;
+            },
+            EEPROM::COMMAND => {
+                self.shift = self.shift << 1 | bit;
+                self.bit_count += 1;
+                if self.bit_count == 10 { self.run_command() }
+            },
+            EEPROM::READ => {
+                self.data_out = self.shift & 0x8000 != 0;
+                self.shift <<= 1;
+                self.bit_count += 1;
+                // Reading continues with the next word
+                if self.bit_count == 16 {
+                    self.address = (self.address + 1) & 0x7F;
+                    self.shift = self.word(self.address) as u32;
+                    self.bit_count = 0;
+                }
+            },
+            EEPROM::WRITE | EEPROM::WRITE_ALL => {
+                self.shift = self.shift << 1 | bit;
+                self.bit_count += 1;
+                if self.bit_count == 16 {
+                    if self.is_write_enabled {
+                        let word = self.shift as u16;
+                        if self.state == EEPROM::WRITE_ALL {
+                            for address in 0..0x80 { self.set_word(address, word) }
+                        } else {
+                            self.set_word(self.address, word);
+                        }
+                    }
+                    self.state = EEPROM::IDLE;
+                    self.data_out = true;
+                }
+            },
+            _ => {},
+        }
+    }
+
+    fn run_command(&mut self) {
+        let opcode = self.shift >> 8;
+        let extended_opcode = (self.shift >> 6) & 0x3;
+        self.address = self.shift as usize & 0x7F;
+        self.shift = 0;
+        self.bit_count = 0;
+        self.state = EEPROM::IDLE;
+        match opcode {
+            0b10 => {
+                // A dummy 0 comes before the data
+                self.state = EEPROM::READ;
+                self.shift = self.word(self.address) as u32;
+                self.data_out = false;
+            },
+            0b01 => self.state = EEPROM::WRITE,
+            0b11 => if self.is_write_enabled { self.set_word(self.address, 0xFFFF) },
+            _ => match extended_opcode {
+                0b00 => self.is_write_enabled = false,
+                0b01 => self.state = EEPROM::WRITE_ALL,
+                0b10 => if self.is_write_enabled {
+                    self.data = [0xFF; 0x100];
+                    self.is_dirty = true;
+                },
+                _ => self.is_write_enabled = true,
+            },
+        }
+    }
+
+    fn word(&self, address: usize) -> u16 {
+        u16::from_le_bytes([self.data[address * 2], self.data[address * 2 + 1]])
+    }
+
+    fn set_word(&mut self, address: usize, word: u16) {
+        self.data[address * 2..address * 2 + 2].copy_from_slice(&word.to_le_bytes());
+        self.is_dirty = true;
+    }
+}
+
+impl Stateful for EEPROM {
+    fn visit_state(&mut self, state: &mut State) {
+        visit!(state,
+            self.data, self.chip_select, self.clock, self.data_in, self.data_out, self.state, self.shift,
+            self.bit_count, self.address, self.is_write_enabled,
+        );
+        state.check(self.address < 0x80);
+    }
+}
@@ -0,0 +1,169 @@
+use super::MemoryBankController;
+use super::MemoryHandler;
+use super::Header;
+use super::super::super::state::{Stateful, State};
+
+// Multicart mapper. At power on it is unmapped and shows the menu in the last 32KB of the ROM. The menu sets
+// the base ROM and RAM banks of a game and which bank bits the game may change, then maps it, which locks
+// those settings until the next reset. Mapped, it behaves like an MBC1 limited to the game's banks.
+pub struct MMM01 {
+    rom_mask: usize,
+    ram_mask: usize,
+
+    rom: Vec<u8>,
+    rom_bank: usize, // 9 bits, the upper 4 are only writable while unmapped
+    ram_bank: usize, // 4 bits, the upper 2 are only writable while unmapped
+    ram_enable: bool,
+    is_ram_banking: bool,
+    external_ram: Vec<u8>,
+    is_save_dirty: bool, // Battery backed storage changed since the last `take_save_dirty`
+
+    is_mapped: bool,
+    rom_bank_lock: usize, // Bits 1-4 of the ROM bank that the game can't change
+    ram_bank_lock: usize, // Bits 0-1 of the RAM bank that the game can't change
+    is_mode_locked: bool,
+
+    has_ram: bool,
+    has_battery: bool,
+}
+
+impl MMM01 {
+    pub fn new(header: Header, rom: Vec<u8>, has_ram: bool, has_battery: bool) -> Self {
+        let ram_size = header.get_ram_size();
+        let rom_size = header.get_rom_size();
+        MMM01 {
+            rom_mask: rom_size / 0x4000 - 1,
+            ram_mask: if ram_size <= 0x2000 { 0 } else { ram_size / 0x2000 - 1 },
+
+            rom,
+            rom_bank: 0,
+            ram_bank: 0,
+            ram_enable: false,
+            is_ram_banking: false,
+            external_ram: vec![0; ram_size],
+            is_save_dirty: false,
+
+            is_mapped: false,
+            rom_bank_lock: 0,
+            ram_bank_lock: 0,
+            is_mode_locked: false,
+
+            has_ram: has_ram && ram_size > 0,
+            has_battery,
+        }
+    }
+
+    // Banks mapped to 0x0000-0x3FFF and 0x4000-0x7FFF
+    fn rom_banks(&self) -> (usize, usize) {
+        if !self.is_mapped {
+            return (0x1FE & self.rom_mask, 0x1FF & self.rom_mask)
+        }
+        let writable = 0x1F & !self.rom_bank_lock;
+        let mut bank = self.rom_bank;
+        // Like on MBC1, bank 0 of the game can't be mapped to 0x4000-0x7FFF
+        if bank & writable == 0 { bank |= 0x01 }
+        (self.rom_bank & !writable & self.rom_mask, bank & self.rom_mask)
+    }
+
+    fn ram_addr(&self, addr: u16) -> usize {
+        let writable = 0x03 & !self.ram_bank_lock;
+        let bank = if self.is_ram_banking { self.ram_bank } else { self.ram_bank & !writable };
+        ((bank & self.ram_mask) * 0x2000 + (addr as usize - 0xA000)) % self.external_ram.len()
+    }
+}
+
+impl MemoryHandler for MMM01 {
+    fn read(&self, addr: u16) -> u8 {
+        let (bank0, bank) = self.rom_banks();
+        match addr & 0xC000 {
+            0x0000 => self.rom[bank0 * 0x4000 + addr as usize],
+            0x4000 => self.rom[bank * 0x4000 + (addr - 0x4000) as usize],
+            0x8000 => if self.ram_enable && self.has_ram {
+                self.external_ram[self.ram_addr(addr)]
+            } else { 0xFF },
+            _ => panic!("Shouldn't be here!"),
+        }
+    }
+
+    fn write(&mut self, addr: u16, value: u8) {
+        let value = value as usize;
+        match addr & 0xE000 {
+            0x0000 => {
+                self.ram_enable = value & 0x0F == 0x0A;
+                if !self.is_mapped {
+                    self.ram_bank_lock = (value >> 4) & 0x3;
+                    self.is_mapped = value & 0x40 != 0;
+                }
+            },
+            0x2000 => {
+                let writable = if self.is_mapped { 0x1F & !self.rom_bank_lock } else { 0x7F };
+                self.rom_bank = self.rom_bank & !writable | value & writable;
+            },
+            0x4000 => {
+                let writable = if self.is_mapped { 0x03 & !self.ram_bank_lock } else { 0x0F };
+                self.ram_bank = self.ram_bank & !writable | value & writable;
+                if !self.is_mapped {
+                    self.rom_bank = self.rom_bank & 0x7F | (value & 0x30) << 3;
+                    self.is_mode_locked = value & 0x40 != 0;
+                }
+            },
+            0x6000 => {
+                if !self.is_mapped { self.rom_bank_lock = (value & 0x3C) >> 1 }
+                if !(self.is_mapped && self.is_mode_locked) { self.is_ram_banking = value & 0x01 != 0 }
+            },
+            0xA000 => if self.ram_enable && self.has_ram {
+                let addr = self.ram_addr(addr);
+                self.external_ram[addr] = value as u8;
+                self.is_save_dirty = true;
+            },
+            _ => panic!("Shouldn't be here!"),
+        }
+    }
+}
+
+impl MemoryBankController for MMM01 {
+    fn emulate_clock(&mut self) {}
+
+    fn save_data(&self) -> Option<Vec<u8>> {
+        if self.has_battery { Some(self.external_ram.to_vec()) } else { None }
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) {
+        let len = data.len().min(self.external_ram.len());
+        self.external_ram[..len].copy_from_slice(&data[..len]);
+    }
+
+    fn take_save_dirty(&mut self) -> bool {
+        std::mem::replace(&mut self.is_save_dirty, false)
+    }
+
+    fn external_ram(&mut self) -> &mut [u8] {
+        &mut self.external_ram
+    }
+
+    // Everything is written unmapped first and mapping last locks the settings like the menu does,
+    // so the locked settings are only restored on a cartridge that hasn't been mapped yet
+    fn bank_writes(&self) -> Vec<(u16, u8)> {
+        let ram_enable = if self.ram_enable { 0x0A } else { 0x00 };
+        let mut writes = vec![
+            (0x6000, (self.rom_bank_lock << 1) as u8 | self.is_ram_banking as u8),
+            (0x2000, self.rom_bank as u8 & 0x7F),
+            (0x4000, self.ram_bank as u8 | (self.rom_bank >> 3) as u8 & 0x30 | if self.is_mode_locked { 0x40 } else { 0 }),
+            (0x0000, ram_enable | (self.ram_bank_lock << 4) as u8),
+        ];
+        if self.is_mapped {
+            writes.push((0x0000, ram_enable | (self.ram_bank_lock << 4) as u8 | 0x40));
+        }
+        writes
+    }
+}
+
+impl Stateful for MMM01 {
+    fn visit_state(&mut self, state: &mut State) {
+        visit!(state,
+            self.rom_bank, self.ram_bank, self.ram_enable, self.is_ram_banking, self.external_ram[..],
+            self.is_mapped, self.rom_bank_lock, self.ram_bank_lock, self.is_mode_locked,
+        );
+        state.check(self.rom_bank <= 0x1FF && self.ram_bank <= 0xF && self.rom_bank_lock <= 0x1E && self.ram_bank_lock <= 0x3);
+    }
+}
@@ -2,17 +2,69 @@ mod none;
 mod mbc1;
 mod mbc2;
 mod mbc3;
+mod mbc5;
+mod mbc6;
+mod mbc7;
+mod mmm01;
+mod huc1;
+mod huc3;
+mod camera;
 
 use super::MemoryHandler;
 use super::Header;
+use super::super::LoadError;
+use super::super::state::Stateful;
+use super::super::infrared::InfraredLink;
+use super::super::camera::CameraSource;
 
-pub trait MemoryBankController: MemoryHandler {
-    fn get_boot_rom_ptr(&mut self) -> *mut [u8; 0x900];
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub trait MemoryBankController: MemoryHandler + Stateful {
     fn emulate_clock(&mut self);
+
+    // Battery backed state in .sav file layout, if the cartridge has a battery
+    fn save_data(&self) -> Option<Vec<u8>>;
+    fn load_save_data(&mut self, data: &[u8]);
+    // Whether the battery backed state changed since the last call, so the save file only gets rewritten then
+    fn take_save_dirty(&mut self) -> bool;
+
+    // Cartridge RAM regardless of battery, empty if there is none
+    fn external_ram(&mut self) -> &mut [u8];
+    // Register writes that restore the current banking when replayed in order
+    fn bank_writes(&self) -> Vec<(u16, u8)>;
+
+    // Clock registers and timestamp in the common 48 byte RTC layout, for mappers with a clock
+    fn rtc_data(&self) -> Option<Vec<u8>> { None }
+    fn load_rtc_data(&mut self, _data: &[u8]) {}
+
+    // Called with the new motor state whenever a rumble cartridge turns its motor on or off
+    fn set_rumble_callback(&mut self, _rumble_callback: Box<dyn FnMut(bool)>) {}
+
+    // Connects the infrared LED and receiver of cartridges that have them
+    fn set_infrared_link(&mut self, _infrared_link: Box<dyn InfraredLink>) {}
+
+    // Output of a speaker on the cartridge from -1.0 to 1.0, mixed into the audio every machine cycle
+    fn speaker_level(&self) -> f32 { 0.0 }
+
+    // Tilt of the cartridge in g along the X and Y axes, for cartridges with an accelerometer
+    fn set_tilt(&mut self, _x: f32, _y: f32) {}
+
+    // Supplies the pictures taken by cartridges with a camera
+    fn set_camera_source(&mut self, _source: Box<dyn CameraSource>) {}
+}
+
+// Seconds since the unix epoch, stored with cartridge clocks to catch up on time that passed while not running
+fn unix_time() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.as_secs()).unwrap_or(0)
 }
 
-pub fn get_mbc(header: Header, rom: Vec<u8>) -> Box<dyn MemoryBankController> {
-    match header.get_cartridge_type() {
+pub fn get_mbc(header: Header, rom: Vec<u8>) -> Result<Box<dyn MemoryBankController>, LoadError> {
+    let cartridge_type = header.get_cartridge_type();
+    if cartridge_type != 0x00 && cartridge_type != 0x08 && cartridge_type != 0x09 && header.get_rom_size() != rom.len() {
+        return Err(LoadError::SizeMismatch { expected: header.get_rom_size(), actual: rom.len() });
+    }
+
+    Ok(match cartridge_type {
         0x00 => Box::new(none::None::new(header, rom, false, false)),
         0x01 => Box::new(mbc1::MBC1::new(header, rom, false, false)),
         0x02 => Box::new(mbc1::MBC1::new(header, rom, true, false)),
@@ -21,12 +73,25 @@ pub fn get_mbc(header: Header, rom: Vec<u8>) -> Box<dyn MemoryBankController> {
         0x06 => Box::new(mbc2::MBC2::new(header, rom, true)),
         0x08 => Box::new(none::None::new(header, rom, true, false)),
         0x09 => Box::new(none::None::new(header, rom, true, true)),
-        // TODO: Implement MMM01
-        0x0F => Box::new(mbc3::MBC3::new(header, rom, false, false, true)),
+        0x0B => Box::new(mmm01::MMM01::new(header, rom, false, false)),
+        0x0C => Box::new(mmm01::MMM01::new(header, rom, true, false)),
+        0x0D => Box::new(mmm01::MMM01::new(header, rom, true, true)),
+        0x0F => Box::new(mbc3::MBC3::new(header, rom, true, false, true)),
         0x10 => Box::new(mbc3::MBC3::new(header, rom, true, true, true)),
         0x11 => Box::new(mbc3::MBC3::new(header, rom, false, false, false)),
         0x12 => Box::new(mbc3::MBC3::new(header, rom, false, true, false)),
         0x13 => Box::new(mbc3::MBC3::new(header, rom, false, true, true)),
-        _ => panic!("Unsupported Cartridge Type {:X}", header.get_cartridge_type()),
-    }
+        0x19 => Box::new(mbc5::MBC5::new(header, rom, false, false, false)),
+        0x1A => Box::new(mbc5::MBC5::new(header, rom, true, false, false)),
+        0x1B => Box::new(mbc5::MBC5::new(header, rom, true, true, false)),
+        0x1C => Box::new(mbc5::MBC5::new(header, rom, false, false, true)),
+        0x1D => Box::new(mbc5::MBC5::new(header, rom, true, false, true)),
+        0x1E => Box::new(mbc5::MBC5::new(header, rom, true, true, true)),
+        0x20 => Box::new(mbc6::MBC6::new(header, rom)),
+        0x22 => Box::new(mbc7::MBC7::new(header, rom)),
+        0xFC => Box::new(camera::Camera::new(header, rom)),
+        0xFE => Box::new(huc3::HuC3::new(header, rom)),
+        0xFF => Box::new(huc1::HuC1::new(header, rom)),
+        _ => return Err(LoadError::UnsupportedMapper(cartridge_type)),
+    })
 }
@@ -1,13 +1,15 @@
 use super::MemoryBankController;
 use super::MemoryHandler;
 use super::Header;
+use super::super::super::state::{Stateful, State};
 
 pub struct None {
     rom: Vec<u8>,
     ram: [u8; 0x2000],
+    is_save_dirty: bool, // Battery backed storage changed since the last `take_save_dirty`
 
     has_ram: bool,
-    _has_battery: bool,
+    has_battery: bool,
 }
 
 impl None {
@@ -15,9 +17,10 @@ impl None {
         None {
             rom,
             ram: [0; 0x2000],
+            is_save_dirty: false,
 
             has_ram,
-            _has_battery: has_battery,
+            has_battery,
         }
     }
 }
@@ -31,17 +34,44 @@ impl MemoryHandler for None {
         } else { 0xFF }
     }
     
+    #[allow(clippy::manual_range_contains)]
     fn write(&mut self, addr: u16, value: u8) {
         if addr >= 0xA000 && addr < 0xC000 && self.has_ram {
             self.ram[addr as usize - 0xA000] = value;
+            self.is_save_dirty = true;
         }
     }
 }
 
 impl MemoryBankController for None {
-    fn get_boot_rom_ptr(&mut self) -> *mut [u8; 0x900] {
-        self.rom[..0x900].as_mut_ptr() as *mut [u8; 0x900]
+    fn emulate_clock(&mut self) {}
+
+    fn save_data(&self) -> Option<Vec<u8>> {
+        if self.has_battery { Some(self.ram.to_vec()) } else { None }
     }
 
-    fn emulate_clock(&mut self) {}
+    fn load_save_data(&mut self, data: &[u8]) {
+        let len = data.len().min(self.ram.len());
+        self.ram[..len].copy_from_slice(&data[..len]);
+    }
+
+    fn take_save_dirty(&mut self) -> bool {
+        std::mem::replace(&mut self.is_save_dirty, false)
+    }
+
+    fn external_ram(&mut self) -> &mut [u8] {
+        if self.has_ram { &mut self.ram } else { &mut [] }
+    }
+
+    fn bank_writes(&self) -> Vec<(u16, u8)> {
+        Vec::new()
+    }
+}
+
+impl Stateful for None {
+    fn visit_state(&mut self, state: &mut State) {
+        visit!(state,
+            self.ram,
+        );
+    }
 }
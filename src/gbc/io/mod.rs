@@ -1,5 +1,3 @@
-extern crate sdl2;
-
 mod header;
 mod mbc;
 mod apu;
@@ -9,9 +7,6 @@ mod serial;
 mod joypad;
 mod timer;
 
-use sdl2::event::Event;
-use sdl2::event::WindowEvent;
-
 use header::Header;
 use mbc::MemoryBankController;
 use apu::APU;
@@ -23,6 +18,18 @@ use serial::Serial;
 use joypad::Joypad;
 use timer::Timer;
 use ram::HRAM;
+use super::VideoSink;
+use super::AudioSink;
+use super::InfraredLink;
+use super::CameraSource;
+use super::LoadError;
+use super::Model;
+use super::state::{Stateful, State};
+
+use std::vec::Drain;
+
+pub use ppu::{WIDTH, HEIGHT};
+pub use joypad::Button;
 
 pub trait MemoryHandler {
     fn read(&self, addr: u16) -> u8;
@@ -42,31 +49,37 @@ pub struct IO {
     hram: HRAM,
     pub int_flags: u8,
     unusable: Unusable,
+    boot_rom: Option<Vec<u8>>,
 
-    in_cgb: bool,
+    model: Model,
+    in_cgb: bool, // CGB mode, rather than DMG compatibility mode
+    key0: u8, // Bit 2 picks DMG compatibility mode when the boot rom is unmapped
     double_speed: bool,
     prepare_speed_switch: bool,
     in_gdma: bool,
     in_hdma: bool,
+    tilt: [f32; 2], // Passed on to the mapper, kept here so it is part of the state like the buttons
 
     // Other
-    pub sdl_ctx: sdl2::Sdl,
     pub c: u128,
-    pub should_close: bool,
+    pub frame_ready: bool,
+    #[allow(dead_code)]
     pub p: bool,
 }
 
 impl IO {
-    pub fn new(rom: Vec<u8>) -> Self {
-        let header = Header::new(&rom);
-        let in_cgb = header.in_cgb();
-        let sdl_ctx = sdl2::init().unwrap();
-
-        IO {
-            mbc: mbc::get_mbc(header, rom),
-            ppu: if in_cgb { Box::new(CgbPPU::new(&sdl_ctx)) } else { Box::new(GbPPU::new(&sdl_ctx)) },
-            apu: APU::new(&sdl_ctx),
-            wram: WRAM::new(if in_cgb { 8 } else { 2 }),
+    pub fn new(rom: Vec<u8>, model: Option<Model>) -> Result<Self, LoadError> {
+        let header = Header::new(&rom)?;
+        let model = model.unwrap_or(if header.in_cgb() { Model::Cgb } else { Model::Dmg });
+        // Without a boot rom the mode the boot rom would pick through KEY0 is set right away. A boot rom
+        // itself runs in CGB mode and picks the mode by writing KEY0 before unmapping.
+        let in_cgb = model.is_cgb() && header.in_cgb();
+
+        Ok(IO {
+            mbc: mbc::get_mbc(header, rom)?,
+            ppu: if model.is_cgb() { Box::new(CgbPPU::new(!in_cgb)) } else { Box::new(GbPPU::new()) },
+            apu: APU::new(),
+            wram: WRAM::new(if model.is_cgb() { 8 } else { 2 }),
             serial: Serial::new(),
             joypad: Joypad::new(),
             timer: Timer::new(),
@@ -74,22 +87,26 @@ impl IO {
             hram: HRAM::new(),
             int_flags: 0,
             unusable: Unusable {},
+            boot_rom: None,
 
+            model,
             in_cgb,
+            key0: if in_cgb { 0x80 } else { 0x04 },
+            tilt: [0.0; 2],
             double_speed: false,
             prepare_speed_switch: false,
             in_gdma: false,
             in_hdma: false,
 
-            sdl_ctx,
             c: 8,
-            should_close: false,
+            frame_ready: false,
             p: false,
-        }
+        })
     }
 
     pub fn read(&self, addr: u16) -> u8 {
         match addr {
+            0x0000 ..= 0x08FF if self.boot_rom_mapped(addr) => self.boot_rom.as_ref().unwrap()[addr as usize],
             0x0000 ..= 0x7FFF => self.mbc.read(addr),
             0x8000 ..= 0x9FFF => self.ppu.read(addr),
             0xA000 ..= 0xBFFF => self.mbc.read(addr),
@@ -103,10 +120,10 @@ impl IO {
             0xFF10 ..= 0xFF26 => self.apu.read(addr),
             0xFF40 ..= 0xFF4B => self.ppu.read(addr),
             0xFF4D => if self.in_cgb { 0x7E | (self.double_speed as u8) << 7 | self.prepare_speed_switch as u8 } else { 0xFF },
-            0xFF4F => self.ppu.read_vram_bank(),
-            0xFF55 => self.ppu.read_hdma(),
-            0xFF68 ..= 0xFF6B => self.ppu.read_cgb_palettes(addr),
-            0xFF70 => self.wram.read_bank(),
+            0xFF4F if self.in_cgb => self.ppu.read_vram_bank(),
+            0xFF55 if self.in_cgb => self.ppu.read_hdma(),
+            0xFF68 ..= 0xFF6B if self.in_cgb => self.ppu.read_cgb_palettes(addr),
+            0xFF70 if self.in_cgb => self.wram.read_bank(),
             0xFF80 ..= 0xFFFE => self.hram.read(addr),
             0xFFFF => self.int_enable,
             _ => self.unusable.read(addr),
@@ -127,11 +144,13 @@ impl IO {
             0xFF0F => self.int_flags = value | 0xE0,
             0xFF10 ..= 0xFF26 => self.apu.write(addr, value),
             0xFF40 ..= 0xFF4B => self.ppu.write(addr, value),
+            0xFF4C if self.model.is_cgb() && self.boot_rom.is_some() => self.key0 = value,
             0xFF4D => self.prepare_speed_switch = value & 0x1 != 0,
-            0xFF4F => self.ppu.write_vram_bank(value),
-            0xFF51 ..= 0xFF55 => self.ppu.write_hdma(addr, value, self.double_speed),
-            0xFF68 ..= 0xFF6B => self.ppu.write_cgb_palettes(addr, value),
-            0xFF70 => self.wram.write_bank(value),
+            0xFF4F if self.in_cgb => self.ppu.write_vram_bank(value),
+            0xFF50 => if value & 0x1 != 0 { self.unmap_boot_rom() },
+            0xFF51 ..= 0xFF55 if self.in_cgb => self.ppu.write_hdma(addr, value, self.double_speed),
+            0xFF68 ..= 0xFF6B if self.in_cgb => self.ppu.write_cgb_palettes(addr, value),
+            0xFF70 if self.in_cgb => self.wram.write_bank(value),
             0xFF80 ..= 0xFFFE => self.hram.write(addr, value),
             0xFFFF => self.int_enable = value,
             _ => self.unusable.write(addr, value),
@@ -144,37 +163,18 @@ impl IO {
         self.oam_dma();
         self.hdma();
         self.gdma();
-        self.int_flags |= self.ppu.emulate_clock();
-        self.int_flags |= self.ppu.emulate_clock();
-        self.int_flags |= self.ppu.emulate_clock();
-        self.int_flags |= self.ppu.emulate_clock();
+        let mut ppu_interrupts = 0;
+        ppu_interrupts |= self.ppu.emulate_clock();
+        ppu_interrupts |= self.ppu.emulate_clock();
+        ppu_interrupts |= self.ppu.emulate_clock();
+        ppu_interrupts |= self.ppu.emulate_clock();
+        if ppu_interrupts & IO::VBLANK_INT != 0 { self.frame_ready = true; }
+        self.int_flags |= ppu_interrupts;
+        self.apu.set_cartridge_level(self.mbc.speaker_level());
         self.apu.emulate_clock();
         self.mbc.emulate_clock();
-
-        if self.c % 10000 == 0 {
-            let mut keyboard_events: Vec<Event> = Vec::new();
-            for event in self.sdl_ctx.event_pump().unwrap().poll_iter() {
-                match event {
-                    Event::Quit {..} => {
-                        self.should_close = true;
-                    },
-                    Event::Window { win_event: WindowEvent::Resized(width, height), .. } => {
-                        self.ppu.set_screen_size(width, height)}
-                    Event::KeyDown { keycode: Some(sdl2::keyboard::Keycode::LCtrl), .. } => {
-                        self.ppu._rendering_map(true) },
-                    /*Event::KeyUp { keycode: Some(sdl2::keyboard::Keycode::LCtrl), .. } => {
-                        self.ppu._rendering_map(false) },*/
-                    /*Event::KeyUp { keycode: Some(sdl2::keyboard::Keycode::LShift), .. } => {
-                        self.p = false },*/
-                    Event::KeyDown {..} => { keyboard_events.push(event) },
-                    Event::KeyUp {..} => { keyboard_events.push(event) },
-                    _ => {},
-                }
-            }
-            self.int_flags |= self.joypad.update_inputs(&keyboard_events);
-        }
     }
-    
+
     pub fn stop(&mut self) {
         if self.in_cgb && self.prepare_speed_switch {
             self.prepare_speed_switch = false;
@@ -187,21 +187,229 @@ impl IO {
         }
     }
 
-    pub fn swap_boot_rom(&mut self, boot_rom: &mut Vec<u8>) {
-        let boot_rom_len = boot_rom.len();
-        assert_eq!(boot_rom_len, 0x900);
+    pub fn model(&self) -> Model {
+        self.model
+    }
+
+    pub fn in_cgb(&self) -> bool {
+        self.in_cgb
+    }
+
+    pub fn save_data(&self) -> Option<Vec<u8>> {
+        self.mbc.save_data()
+    }
+
+    pub fn take_save_dirty(&mut self) -> bool {
+        self.mbc.take_save_dirty()
+    }
+
+    pub fn load_save_data(&mut self, data: &[u8]) {
+        self.mbc.load_save_data(data);
+    }
+
+    pub fn frame(&self) -> &[u8] {
+        self.ppu.frame()
+    }
+
+    pub fn set_video_sink(&mut self, video_sink: Box<dyn VideoSink>) {
+        self.ppu.set_video_sink(video_sink);
+    }
+
+    pub fn set_audio_sink(&mut self, audio_sink: Box<dyn AudioSink>) {
+        self.apu.set_audio_sink(audio_sink);
+    }
+
+    pub fn drain_samples(&mut self) -> Drain<'_, f32> {
+        self.apu.drain_samples()
+    }
+
+    pub fn samples(&self) -> &[f32] {
+        self.apu.samples()
+    }
+
+    pub fn clear_samples(&mut self) {
+        self.apu.clear_samples();
+    }
+
+    pub fn set_rumble_callback(&mut self, rumble_callback: Box<dyn FnMut(bool)>) {
+        self.mbc.set_rumble_callback(rumble_callback);
+    }
+
+    pub fn set_infrared_link(&mut self, infrared_link: Box<dyn InfraredLink>) {
+        self.mbc.set_infrared_link(infrared_link);
+    }
+
+    pub fn set_tilt(&mut self, x: f32, y: f32) {
+        self.tilt = [x, y];
+        self.mbc.set_tilt(x, y);
+    }
+
+    pub fn get_tilt(&self) -> (f32, f32) {
+        (self.tilt[0], self.tilt[1])
+    }
+
+    pub fn set_camera_source(&mut self, source: Box<dyn CameraSource>) {
+        self.mbc.set_camera_source(source);
+    }
+
+    pub fn set_button(&mut self, button: Button, pressed: bool) {
+        self.int_flags |= self.joypad.set_button(button, pressed);
+    }
+
+    pub fn set_buttons(&mut self, buttons: u8) {
+        self.int_flags |= self.joypad.set_buttons(buttons);
+    }
+
+    pub fn get_buttons(&self) -> u8 {
+        self.joypad.get_buttons()
+    }
+
+    pub fn _rendering_map(&mut self, rendering_map: bool) {
+        self.ppu._rendering_map(rendering_map);
+    }
+
+    // Maps a 0x100 byte DMG or 0x900 byte CGB boot rom over the cartridge until it is disabled through 0xFF50
+    pub fn map_boot_rom(&mut self, boot_rom: Vec<u8>) {
+        self.boot_rom = Some(boot_rom);
+        if self.model.is_cgb() { self.set_cgb_mode(true) }
+    }
+
+    // Unmapping locks in the mode picked through KEY0
+    fn unmap_boot_rom(&mut self) {
+        if self.boot_rom.take().is_some() && self.model.is_cgb() {
+            self.set_cgb_mode(self.key0 & 0x04 == 0);
+        }
+    }
+
+    fn set_cgb_mode(&mut self, in_cgb: bool) {
+        self.in_cgb = in_cgb;
+        self.ppu.set_dmg_mode(!in_cgb);
+    }
+
+    fn boot_rom_mapped(&self, addr: u16) -> bool {
+        match &self.boot_rom {
+            Some(boot_rom) => addr < 0x100 || (addr >= 0x200 && (addr as usize) < boot_rom.len()),
+            None => false,
+        }
+    }
+
+    // Puts IO registers, palettes and VRAM in the state the boot rom leaves them in
+    pub fn skip_boot_rom(&mut self) {
+        self.write(0xFF26, 0x80); // Sound has to be powered on before the other registers can be set
+        for &(addr, value) in IO::POST_BOOT_REGISTERS.iter() {
+            self.write(addr, value);
+        }
+        self.timer.set_divider(if self.model.is_cgb() { 0x1EA0 } else { 0xABCC });
+
+        // The logo tiles are decoded from the cartridge header, upscaled by two in each direction
+        for i in 0..0x30 {
+            let byte = self.read(0x0104 + i);
+            for (j, nibble) in [byte >> 4, byte & 0xF].iter().enumerate() {
+                let row = (0..8).fold(0, |acc, bit| acc | ((nibble >> (bit / 2)) & 0x1) << bit);
+                let addr = 0x8010 + i * 8 + j as u16 * 4;
+                self.write(addr, row);
+                self.write(addr + 2, row);
+            }
+        }
+        for (i, &row) in IO::REGISTERED_TILE.iter().enumerate() {
+            self.write(0x8190 + i as u16 * 2, row);
+        }
+
+        if self.in_cgb {
+            // CGB games start with a cleared tile map and white background palettes
+            self.write(0xFF68, 0x80);
+            for _ in 0..0x40 { self.write(0xFF69, 0xFF); }
+        } else {
+            self.write(0x9910, 0x19);
+            for i in 0..12 {
+                self.write(0x9904 + i, i as u8 + 0x01);
+                self.write(0x9924 + i, i as u8 + 0x0D);
+            }
+            if self.model.is_cgb() {
+                // Compatibility palettes picked for games without a known title.
+                // The palette registers are locked in compatibility mode, so these go straight to the PPU.
+                self.ppu.write_cgb_palettes(0xFF68, 0x80);
+                self.ppu.write_cgb_palettes(0xFF6A, 0x80);
+                for &color in IO::COMPATIBILITY_BG_PALETTE.iter() {
+                    self.ppu.write_cgb_palettes(0xFF69, color as u8);
+                    self.ppu.write_cgb_palettes(0xFF69, (color >> 8) as u8);
+                }
+                for &color in IO::COMPATIBILITY_OBJ_PALETTE.iter().chain(IO::COMPATIBILITY_OBJ_PALETTE.iter()) {
+                    self.ppu.write_cgb_palettes(0xFF6B, color as u8);
+                    self.ppu.write_cgb_palettes(0xFF6B, (color >> 8) as u8);
+                }
+            }
+        }
+    }
+
+    // Raw memory regions in BESS order: WRAM, VRAM, cartridge RAM, OAM, HRAM, BG palettes and OBJ palettes
+    pub fn memory_regions(&mut self) -> Vec<Vec<u8>> {
+        let (bg_palettes, obj_palettes) = self.ppu.cgb_palettes();
+        let (bg_palettes, obj_palettes) = (bg_palettes.to_vec(), obj_palettes.to_vec());
+        vec![
+            self.wram.mem().to_vec(), self.ppu.vram().to_vec(), self.mbc.external_ram().to_vec(),
+            self.ppu.oam().to_vec(), self.hram.mem().to_vec(), bg_palettes, obj_palettes,
+        ]
+    }
+
+    // Copies as much of each region as fits
+    pub fn load_memory_regions(&mut self, regions: &[&[u8]]) {
+        fn copy(dest: &mut [u8], src: &[u8]) {
+            let len = dest.len().min(src.len());
+            dest[..len].copy_from_slice(&src[..len]);
+        }
+        copy(self.wram.mem(), regions[0]);
+        copy(self.ppu.vram(), regions[1]);
+        copy(self.mbc.external_ram(), regions[2]);
+        copy(self.ppu.oam(), regions[3]);
+        copy(self.hram.mem(), regions[4]);
+        self.ppu.load_cgb_palettes(regions[5], regions[6]);
+    }
+
+    // The memory mapped registers from 0xFF00 to 0xFF7F as the CPU reads them
+    pub fn registers(&self) -> [u8; 0x80] {
+        let mut registers = [0; 0x80];
+        for (i, register) in registers.iter_mut().enumerate() {
+            *register = self.read(0xFF00 + i as u16);
+        }
+        registers
+    }
 
-        let rom = self.mbc.get_boot_rom_ptr();
-        let boot_rom_a = boot_rom[..0x100].as_mut_ptr() as *mut [u8; 0x100];
-        let boot_rom_b = boot_rom[0x200..0x900].as_mut_ptr() as *mut [u8; 0x700];
-        unsafe {
-            let rom_a = (*rom)[..0x100].as_mut_ptr() as *mut [u8; 0x100];
-            let rom_b = (*rom)[0x200..0x900].as_mut_ptr() as *mut [u8; 0x700];
-            std::ptr::swap(rom_a, boot_rom_a);
-            std::ptr::swap(rom_b, boot_rom_b);
+    // Writes back registers from `registers` without starting DMAs, triggering sound channels or serial transfers
+    pub fn load_registers(&mut self, registers: &[u8]) {
+        let register = |addr: u16| registers[addr as usize - 0xFF00];
+        self.write(0xFF26, register(0xFF26)); // Sound has to be powered on before the other registers can be set
+        for addr in 0xFF00..0xFF80 {
+            let value = register(addr);
+            match addr {
+                0xFF02 => self.write(addr, value & 0x7F),
+                0xFF04 => self.timer.set_divider((value as u16) << 8),
+                0xFF14 | 0xFF19 | 0xFF1E | 0xFF23 => self.write(addr, value & 0x7F),
+                0xFF26 | 0xFF44 | 0xFF46 | 0xFF4C | 0xFF55 | 0xFF69 | 0xFF6B => {},
+                0xFF4D => if self.in_cgb {
+                    self.double_speed = value & 0x80 != 0;
+                    self.prepare_speed_switch = value & 0x1 != 0;
+                    self.ppu.set_double_speed(self.double_speed);
+                    self.apu.set_double_speed(self.double_speed);
+                },
+                0xFF50 => if value & 0x1 != 0 { self.unmap_boot_rom() },
+                _ => self.write(addr, value),
+            }
         }
     }
 
+    pub fn bank_writes(&self) -> Vec<(u16, u8)> {
+        self.mbc.bank_writes()
+    }
+
+    pub fn rtc_data(&self) -> Option<Vec<u8>> {
+        self.mbc.rtc_data()
+    }
+
+    pub fn load_rtc_data(&mut self, data: &[u8]) {
+        self.mbc.load_rtc_data(data);
+    }
+
     fn oam_dma(&mut self) {
         if !self.ppu.in_oam_dma() { return }
         let (should_write, oam_addr, cpu_addr)  = self.ppu.oam_dma();
@@ -248,10 +456,46 @@ impl IO {
     pub const _SERIAL_INT: u8 = 1 << 3;
     pub const JOYPAD_INT: u8 = 1 << 4;
 
+    // Channel 1 is left untriggered so the boot sound isn't replayed
+    const POST_BOOT_REGISTERS: [(u16, u8); 22] = [
+        (0xFF05, 0x00), (0xFF06, 0x00), (0xFF07, 0xF8), (0xFF0F, 0xE1),
+        (0xFF10, 0x80), (0xFF11, 0xBF), (0xFF12, 0xF3), (0xFF13, 0xFF),
+        (0xFF16, 0x3F), (0xFF17, 0x00), (0xFF18, 0xFF),
+        (0xFF1A, 0x7F), (0xFF1B, 0xFF), (0xFF1C, 0x9F), (0xFF1D, 0xFF),
+        (0xFF20, 0xFF), (0xFF21, 0x00), (0xFF22, 0x00),
+        (0xFF24, 0x77), (0xFF25, 0xF3),
+        (0xFF40, 0x91), (0xFF47, 0xFC),
+    ];
+    const REGISTERED_TILE: [u8; 8] = [0x3C, 0x42, 0xB9, 0xA5, 0xB9, 0xA5, 0x42, 0x3C];
+    const COMPATIBILITY_BG_PALETTE: [u16; 4] = [0x7FFF, 0x1BEF, 0x6180, 0x0000];
+    const COMPATIBILITY_OBJ_PALETTE: [u16; 4] = [0x7FFF, 0x421F, 0x1CF2, 0x0000];
+
     const GB_CLOCK_SPEED: u32 = 4194304 / 4;
     const GBC_CLOCK_SPEED: u32 = 8388608 / 4;
 }
 
+impl Stateful for IO {
+    fn visit_state(&mut self, state: &mut State) {
+        visit!(state,
+            self.int_enable, self.int_flags, self.double_speed, self.prepare_speed_switch,
+            self.in_gdma, self.in_hdma, self.c, self.boot_rom, self.in_cgb, self.key0, self.tilt,
+        );
+        self.mbc.visit_state(state);
+        self.apu.visit_state(state);
+        self.ppu.visit_state(state);
+        self.wram.visit_state(state);
+        self.hram.visit_state(state);
+        self.serial.visit_state(state);
+        self.joypad.visit_state(state);
+        self.timer.visit_state(state);
+        if state.is_loading() {
+            self.ppu.set_double_speed(self.double_speed);
+            if self.model.is_cgb() { self.ppu.set_dmg_mode(!self.in_cgb) }
+            self.mbc.set_tilt(self.tilt[0], self.tilt[1]);
+        }
+    }
+}
+
 struct Unusable;
 impl MemoryHandler for Unusable {
     fn read(&self, _addr: u16) -> u8 { 0xFF }
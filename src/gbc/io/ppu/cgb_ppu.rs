@@ -1,9 +1,13 @@
 use super::MemoryHandler;
 use super::IO;
-use super::screen::Screen;
+use super::VideoSink;
+use super::super::super::video::NullSink;
+use super::{WIDTH, new_frame};
 use super::PPU;
+use super::super::super::state::{Stateful, State};
 
 pub struct CgbPPU {
+    dmg_mode: bool, // Compatibility mode for DMG games, colored through the DMG palettes
     // Registers
     // Control
     lcd_enable: bool,
@@ -77,7 +81,9 @@ pub struct CgbPPU {
     vram: [[u8; 0x2000]; 2],
     vram_bank: usize,
     pub oam: [u8; 0xA0],
-    screen: Screen,
+    pixels: Vec<u8>,
+    frame: Vec<u8>,
+    video_sink: Box<dyn VideoSink>,
     pub _rendering_map: bool,
     _rendered_map: bool,
 }
@@ -185,10 +191,6 @@ impl PPU for CgbPPU {
         interrupt
     }
 
-    fn set_screen_size(&mut self, width: i32, height: i32) {
-        self.screen.set_screen_size(width, height);
-    }
-
     fn read_vram_bank(&self) -> u8 {
         (0xFE | self.vram_bank) as u8
     }
@@ -288,9 +290,21 @@ impl PPU for CgbPPU {
     }
 
     fn set_double_speed(&mut self, double_speed: bool) {
-        self.screen.set_double_speed(double_speed);
+        self.video_sink.set_double_speed(double_speed);
+    }
+
+    fn set_dmg_mode(&mut self, dmg_mode: bool) {
+        self.dmg_mode = dmg_mode;
     }
     
+    fn frame(&self) -> &[u8] {
+        &self.frame
+    }
+
+    fn set_video_sink(&mut self, video_sink: Box<dyn VideoSink>) {
+        self.video_sink = video_sink;
+    }
+
     fn in_oam_dma(&self) -> bool {
         self.in_oam_dma
     }
@@ -362,11 +376,33 @@ impl PPU for CgbPPU {
     }
 
     fn _rendering_map(&mut self, _rendering_map: bool) { self._rendering_map = _rendering_map }
+
+    fn vram(&mut self) -> &mut [u8] {
+        self.vram.as_flattened_mut()
+    }
+
+    fn oam(&mut self) -> &mut [u8] {
+        &mut self.oam
+    }
+
+    fn cgb_palettes(&self) -> (&[u8], &[u8]) {
+        (&self.bg_palettes, &self.obj_palettes)
+    }
+
+    fn load_cgb_palettes(&mut self, bg_palettes: &[u8], obj_palettes: &[u8]) {
+        let bg_len = bg_palettes.len().min(0x40);
+        let obj_len = obj_palettes.len().min(0x40);
+        self.bg_palettes[..bg_len].copy_from_slice(&bg_palettes[..bg_len]);
+        self.obj_palettes[..obj_len].copy_from_slice(&obj_palettes[..obj_len]);
+        self.bg_colors = CgbPPU::palette_colors(&self.bg_palettes);
+        self.obj_colors = CgbPPU::palette_colors(&self.obj_palettes);
+    }
 }
 
 impl CgbPPU {
-    pub fn new(sdl_ctx: &sdl2::Sdl) -> Self {
+    pub fn new(dmg_mode: bool) -> Self {
         CgbPPU {
+            dmg_mode,
             // Registers
             // Control
             lcd_enable: false,
@@ -440,12 +476,27 @@ impl CgbPPU {
             vram: [[0; 0x2000]; 2],
             vram_bank: 0,
             oam: [0; 0xA0],
-            screen: Screen::new(sdl_ctx),
+            pixels: new_frame(),
+            frame: new_frame(),
+            video_sink: Box::new(NullSink),
             _rendering_map: false,
             _rendered_map: false,
         }
     }
 
+    // Converts little endian RGB555 palettes into RGB888 colors
+    fn palette_colors(palettes: &[u8; 0x40]) -> [[[u8; 3]; 4]; 8] {
+        let mut colors = [[[0; 3]; 4]; 8];
+        for (i, color) in palettes.chunks(2).enumerate() {
+            let value = color[0] as u16 | (color[1] as u16) << 8;
+            for (j, channel) in colors[i / 4][i % 4].iter_mut().enumerate() {
+                *channel = (((value >> (5 * j)) & 0x1F) * 255 / 31) as u8;
+            }
+        }
+        colors
+    }
+
+    #[allow(clippy::collapsible_if)]
     fn render_clock(&mut self) -> u8 {
         let mut interrupt = 0;
         if self.y_coord < 144 && self.y_coord_inc != 0 {
@@ -484,7 +535,8 @@ impl CgbPPU {
             if self.y_coord == 144 && self.clock_num == 4 {
                 self.mode = 1;
                 if self._rendering_map { self._render_map(); }
-                self.screen.render();
+                self.frame.copy_from_slice(&self.pixels);
+                self.video_sink.present(&self.frame);
                 interrupt = IO::VBLANK_INT;
             }
             if self.y_coord == 153 && self.clock_num == 8 {
@@ -539,7 +591,7 @@ impl CgbPPU {
 
     fn render_line(&mut self) {
         if self._rendering_map { return }
-        else if self._rendered_map {for i in self.screen.pixels.iter_mut() { *i = 0 }; self._rendered_map = false; }
+        else if self._rendered_map {for pixel in self.pixels.chunks_mut(4) { pixel[..3].fill(0) }; self._rendered_map = false; }
         fn bg_window_tiles_select_1(tile_num: u8) -> usize { (0x900u16.wrapping_add(tile_num as i8 as u16) << 4) as usize }
         fn bg_window_tiles_select_0(tile_num: u8) -> usize { 0x8000 + ((tile_num as usize) << 4) }
         self.hblank_clock = 255 + self.scroll_x as u16 % 8;
@@ -552,7 +604,7 @@ impl CgbPPU {
         let sprite_height = if self.is8x16 { 16 } else { 8 };
         self.current_sprite_i = 0;
         for x in 0u8..160u8 {
-            let pixel_index = 3 * ((Screen::HEIGHT - 1 - self.y_coord as u32) * Screen::WIDTH + x as u32) as usize;
+            let pixel_index = 4 * (self.y_coord as u32 * WIDTH + x as u32) as usize;
 
             let is_window_pixel = self.window_enable && self.y_coord >= self.window_y && x + 7 >= self.window_x;
 
@@ -567,7 +619,7 @@ impl CgbPPU {
                 (offsetted_x, offsetted_y, bg_map_offset + (offsetted_y / 8 * 32) + map_x as u16)
             };
 
-            let attrs = self.vram[1][map_addr as usize - 0x8000];
+            let attrs = if self.dmg_mode { 0 } else { self.vram[1][map_addr as usize - 0x8000] };
             let bg_palette_num = attrs as usize & 0x7;
             let vram_bank = (attrs & 0x08 != 0) as usize;
             let flip_x = attrs & 0x20 != 0;
@@ -587,7 +639,9 @@ impl CgbPPU {
             let low = (tile_lows >> shift) & 0x1;
             let bg_color = (high << 1 | low) as usize;
 
-            let mut final_color = self.bg_colors[bg_palette_num][bg_color];
+            let mut final_color = if self.dmg_mode {
+                self.bg_colors[0][(self.gb_bg_palette as usize >> (2 * bg_color)) & 0x3]
+            } else { self.bg_colors[bg_palette_num][bg_color] };
             if !is_window_pixel && !bg_priority {
                 // Sprite
                 let mut i = self.current_sprite_i;
@@ -605,7 +659,7 @@ impl CgbPPU {
                             let flip_x = attrs & 0x20 != 0;
                             let tile_addr = (0x8000 | (tile_num as u16) << 4) as usize;
 
-                            let vram_bank = (attrs as usize >> 3) & 0x1;
+                            let vram_bank = if self.dmg_mode { 0 } else { (attrs as usize >> 3) & 0x1 };
                             let tile_addr = if flip_y {
                                 tile_addr + 2 * (sprite_height + sprite_y - 17 - self.y_coord) as usize
                             } else {
@@ -623,11 +677,15 @@ impl CgbPPU {
                             let low = (tile_lows >> tile_x) & 0x1;
         
                             let obj_priority = attrs & 0x80 != 0;
-                            let palette_num = attrs as usize & 0x7;
                             let obj_color = (high << 1 | low) as usize;
                             if obj_color != 0 {
-                                let obj_color = self.obj_colors[palette_num][obj_color];
-                                if obj_priority && self.bg_window_priority {
+                                let obj_color = if self.dmg_mode {
+                                    let (palette_num, gb_palette) = if attrs & 0x10 != 0 {
+                                        (1, self.gb_obj_palette1)
+                                    } else { (0, self.gb_obj_palette0) };
+                                    self.obj_colors[palette_num][(gb_palette as usize >> (2 * obj_color)) & 0x3]
+                                } else { self.obj_colors[attrs as usize & 0x7][obj_color] };
+                                if obj_priority && (self.bg_window_priority || self.dmg_mode) {
                                     if bg_color == 0 {
                                         final_color = obj_color;
                                     }
@@ -641,12 +699,11 @@ impl CgbPPU {
                 }
             }
 
-            for i in 0..3 {
-                self.screen.pixels[pixel_index + i] = final_color[i];
-            }
+            self.pixels[pixel_index..pixel_index + 3].copy_from_slice(&final_color);
         }
     }
 
+    #[allow(clippy::unnecessary_cast)]
     fn _render_map(&mut self) {
         let bg_map_offset: u16 = if self.bg_map_select { 0x9C00 } else { 0x9800 };
         for y in 0u16..32u16 * 8 {
@@ -665,9 +722,9 @@ impl CgbPPU {
                 let low = (tile_lows >> (7 - tile_x)) & 0x1;
                 let bg_color = (high << 1 | low) as usize;
 
-                let pixel_index = 3 * ((Screen::HEIGHT - 1 - y as u32) * Screen::WIDTH + x as u32) as usize;
+                let pixel_index = 4 * (y as u32 * WIDTH + x as u32) as usize;
                 for i in 0..3 {
-                    self.screen.pixels[pixel_index + i] = self.bg_colors[0][bg_color][i];
+                    self.pixels[pixel_index + i] = self.bg_colors[0][bg_color][i];
                 }
             }
         }
@@ -688,13 +745,35 @@ impl CgbPPU {
                 let low = (tile_lows >> (7 - tile_x)) & 0x1;
                 let bg_color = (high << 1 | low) as usize;
 
-                let pixel_index = 3 * ((Screen::HEIGHT - 1 - y as u32) * Screen::WIDTH + x as u32) as usize;
+                let pixel_index = 4 * (y as u32 * WIDTH + x as u32) as usize;
                 let shades = [0xFF, 0xAA, 0x55, 0x00];
                 for i in 0..3 {
-                    self.screen.pixels[pixel_index + i] = shades[bg_color];
+                    self.pixels[pixel_index + i] = shades[bg_color];
                 }
             }
         }
         self._rendered_map = true;
     }
 }
+
+impl Stateful for CgbPPU {
+    fn visit_state(&mut self, state: &mut State) {
+        visit!(state,
+            self.lcd_enable, self.window_map_select, self.window_enable, self.bg_window_tiles_select,
+            self.bg_map_select, self.is8x16, self.obj_enable, self.bg_window_priority,
+            self.enable_coincidence_int, self.enable_oam_int, self.enable_vblank_int, self.enable_hblank_int,
+            self.coincidence_flag, self.mode, self.scroll_y, self.scroll_x, self.y_coord, self.y_coord_comp,
+            self.window_y, self.window_x, self.gb_bg_palette, self.gb_obj_palette0, self.gb_obj_palette1,
+            self.bg_palette_i, self.bg_palette_inc, self.bg_palettes, self.bg_colors, self.obj_palette_i,
+            self.obj_palette_inc, self.obj_palettes, self.obj_colors, self.oam_dma_page, self.hdma_src,
+            self.hdma_dest, self.hdma_started, self.num_dma_blocks, self.clock_num, self.hblank_clock,
+            self.prev_stat_signal, self.lcd_was_off, self.y_coord_inc, self.in_oam_dma, self.oam_dma_clock,
+            self.disable_oam, self.gdma_clock, self.hdma_clock, self.hdma_finished_line, self.visible_sprites,
+            self.visible_sprite_count, self.current_sprite_i, self.coincidence_int, self.oam_int,
+            self.vblank_int, self.hblank_int, self.vram, self.vram_bank, self.oam, self.pixels[..],
+        );
+        state.check(self.vram_bank < 2 && self.bg_palette_i < 0x40 && self.obj_palette_i < 0x40);
+        state.check(self.visible_sprite_count <= 20 && self.current_sprite_i <= 20);
+        if state.is_loading() { self.frame.copy_from_slice(&self.pixels); }
+    }
+}
@@ -1,7 +1,10 @@
 use super::MemoryHandler;
 use super::IO;
-use super::screen::Screen;
+use super::VideoSink;
+use super::super::super::video::NullSink;
+use super::{WIDTH, new_frame};
 use super::PPU;
+use super::super::super::state::{Stateful, State};
 
 pub struct GbPPU {
     // Registers
@@ -57,7 +60,9 @@ pub struct GbPPU {
 
     vram: [u8; 0x2000],
     oam: [u8; 0xA0],
-    screen: Screen,
+    pixels: Vec<u8>,
+    frame: Vec<u8>,
+    video_sink: Box<dyn VideoSink>,
     // pub _rendering_map: bool,
 }
 
@@ -93,6 +98,7 @@ impl MemoryHandler for GbPPU {
         }
     }
 
+    #[allow(clippy::precedence)]
     fn write(&mut self, addr: u16, value: u8) {
         match addr {
             0x8000 ..= 0x9FFF => if self.mode != 3 { self.vram[addr as usize - 0x8000] = value },
@@ -170,10 +176,6 @@ impl PPU for GbPPU {
         interrupt
     }
 
-    fn set_screen_size(&mut self, width: i32, height: i32) {
-        self.screen.set_screen_size(width, height);
-    }
-
     fn read_vram_bank(&self) -> u8 { 0xFF }
     fn read_cgb_palettes(&self, _addr: u16) -> u8 { 0xFF }
     fn read_hdma(&self) -> u8 { 0xFF }
@@ -183,7 +185,17 @@ impl PPU for GbPPU {
     fn write_hdma(&mut self, _addr: u16, _value: u8, _double_speed: bool) {}
 
     fn set_double_speed(&mut self, _double_speed: bool) {}
+
+    fn set_dmg_mode(&mut self, _dmg_mode: bool) {}
     
+    fn frame(&self) -> &[u8] {
+        &self.frame
+    }
+
+    fn set_video_sink(&mut self, video_sink: Box<dyn VideoSink>) {
+        self.video_sink = video_sink;
+    }
+
     fn in_oam_dma(&self) -> bool {
         self.in_oam_dma
     }
@@ -215,10 +227,15 @@ impl PPU for GbPPU {
     fn in_gdma(&self) -> bool { false }
     fn gdma(&mut self, _double_speed: bool) -> (bool, u16, u16) { (false, 0, 0) }
     fn _rendering_map(&mut self, _rendering_map: bool) {}
+
+    fn vram(&mut self) -> &mut [u8] { &mut self.vram }
+    fn oam(&mut self) -> &mut [u8] { &mut self.oam }
+    fn cgb_palettes(&self) -> (&[u8], &[u8]) { (&[], &[]) }
+    fn load_cgb_palettes(&mut self, _bg_palettes: &[u8], _obj_palettes: &[u8]) {}
 }
 
 impl GbPPU {
-    pub fn new(sdl_ctx: &sdl2::Sdl) -> Self {
+    pub fn new() -> Self {
         GbPPU {
             // Registers
             // Control
@@ -274,7 +291,9 @@ impl GbPPU {
 
             vram: [0; 0x2000],
             oam: [0; 0xA0],
-            screen: Screen::new(sdl_ctx),
+            pixels: new_frame(),
+            frame: new_frame(),
+            video_sink: Box::new(NullSink),
             // _rendering_map: false,
         }
     }
@@ -284,6 +303,7 @@ impl GbPPU {
     const SHADES: [[u8; 3]; 4] = [[0xFF, 0xFF, 0xFF], [0xAA, 0xAA, 0xAA], [0x55, 0x55, 0x55], [0x00, 0x00, 0x00]];
 
 
+    #[allow(clippy::collapsible_if)]
     fn render_clock(&mut self) -> u8 {
         let mut interrupt = 0;
         if self.y_coord < 144 && self.y_coord_inc != 0 {
@@ -320,7 +340,8 @@ impl GbPPU {
             if self.y_coord == 144 && self.clock_num == 2 {
                 self.mode = 1;
                 // if self._rendering_map { self._render_map(); }
-                self.screen.render();
+                self.frame.copy_from_slice(&self.pixels);
+                self.video_sink.present(&self.frame);
                 interrupt = IO::VBLANK_INT;
             }
             if self.y_coord == 153 && self.clock_num == 6 {
@@ -386,7 +407,7 @@ impl GbPPU {
         let offsetted_y = self.y_coord.wrapping_add(self.scroll_y) as u16;
         self.current_sprite_i = 0;
         for x in 0u8..160u8 {
-            let pixel_index = 3 * ((Screen::HEIGHT - 1 - self.y_coord as u32) * Screen::WIDTH + x as u32) as usize;
+            let pixel_index = 4 * (self.y_coord as u32 * WIDTH + x as u32) as usize;
 
             let is_window_pixel = self.window_enable && self.y_coord >= self.window_y && x + 7 >= self.window_x;
 
@@ -463,11 +484,12 @@ impl GbPPU {
             }
 
             for i in 0..3 {
-                self.screen.pixels[pixel_index + i] = GbPPU::SHADES[final_color][i];
+                self.pixels[pixel_index + i] = GbPPU::SHADES[final_color][i];
             }
         }
     }
 
+    #[allow(clippy::unnecessary_cast)]
     fn _render_map(&mut self) {
         let bg_map_offset: u16 = if self.bg_map_select { 0x9C00 } else { 0x9800 };
         for y in 0u16..32u16 * 8 {
@@ -486,11 +508,30 @@ impl GbPPU {
                 let low = (tile_lows >> (7 - tile_x)) & 0x1;
                 let bg_color = (high << 1 | low) as usize;
 
-                let pixel_index = 3 * ((Screen::HEIGHT - 1 - y as u32) * Screen::WIDTH + x as u32) as usize;
+                let pixel_index = 4 * (y as u32 * WIDTH + x as u32) as usize;
                 for i in 0..3 {
-                    self.screen.pixels[pixel_index + i] = GbPPU::SHADES[self.bg_palette[bg_color]][i];
+                    self.pixels[pixel_index + i] = GbPPU::SHADES[self.bg_palette[bg_color]][i];
                 }
             }
         }
     }
 }
+
+impl Stateful for GbPPU {
+    fn visit_state(&mut self, state: &mut State) {
+        visit!(state,
+            self.lcd_enable, self.window_map_select, self.window_enable, self.bg_window_tiles_select,
+            self.bg_map_select, self.obj_size, self.obj_enable, self.bg_priority, self.enable_coincidence_int,
+            self.enable_oam_int, self.enable_vblank_int, self.enable_hblank_int, self.coincidence_flag,
+            self.mode, self.scroll_y, self.scroll_x, self.y_coord, self.y_coord_comp, self.window_y,
+            self.window_x, self.bg_palette, self.obj_palettes, self.oam_dma_page, self.clock_num,
+            self.hblank_clock, self.prev_stat_signal, self.lcd_was_off, self.y_coord_inc, self.in_oam_dma,
+            self.oam_dma_clock, self.disable_oam, self.visible_sprites, self.visible_sprite_count,
+            self.current_sprite_i, self.coincidence_int, self.oam_int, self.vblank_int, self.hblank_int,
+            self.vram, self.oam, self.pixels[..],
+        );
+        state.check(self.visible_sprite_count <= 20 && self.current_sprite_i <= 20);
+        state.check(self.bg_palette.iter().chain(self.obj_palettes.iter().flatten()).all(|shade| *shade < 4));
+        if state.is_loading() { self.frame.copy_from_slice(&self.pixels); }
+    }
+}
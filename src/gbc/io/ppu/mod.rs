@@ -1,4 +1,3 @@
-mod screen;
 mod cgb_ppu;
 mod gb_ppu;
 
@@ -6,11 +5,20 @@ pub use cgb_ppu::CgbPPU;
 pub use gb_ppu::GbPPU;
 use super::MemoryHandler;
 use super::IO;
+use super::super::VideoSink;
+use super::super::state::Stateful;
 
-pub trait PPU: MemoryHandler {
-    fn emulate_clock(&mut self) -> u8;
+pub const WIDTH: u32 = 160;
+pub const HEIGHT: u32 = 144;
+
+// Frames are stored top-down in RGBA
+fn new_frame() -> Vec<u8> {
+    [0, 0, 0, 0xFF].repeat(WIDTH as usize * HEIGHT as usize)
+}
 
-    fn set_screen_size(&mut self, width: i32, height: i32);
+#[allow(clippy::upper_case_acronyms)]
+pub trait PPU: MemoryHandler + Stateful {
+    fn emulate_clock(&mut self) -> u8;
 
     fn read_vram_bank(&self) -> u8;
     fn read_cgb_palettes(&self, addr: u16) -> u8;
@@ -21,6 +29,10 @@ pub trait PPU: MemoryHandler {
     fn write_hdma(&mut self, addr: u16, value: u8, double_speed: bool);
 
     fn set_double_speed(&mut self, double_speed: bool);
+    fn set_dmg_mode(&mut self, dmg_mode: bool);
+
+    fn frame(&self) -> &[u8];
+    fn set_video_sink(&mut self, video_sink: Box<dyn VideoSink>);
 
     fn in_oam_dma(&self) -> bool;
     fn oam_dma(&mut self) -> (bool, u16, u16);
@@ -33,4 +45,10 @@ pub trait PPU: MemoryHandler {
     fn hdma(&mut self, double_speed: bool) -> (bool, u16, u16);
 
     fn _rendering_map(&mut self, _rendering_map: bool);
+
+    // Raw memory, used by BESS states
+    fn vram(&mut self) -> &mut [u8];
+    fn oam(&mut self) -> &mut [u8];
+    fn cgb_palettes(&self) -> (&[u8], &[u8]);
+    fn load_cgb_palettes(&mut self, bg_palettes: &[u8], obj_palettes: &[u8]);
 }
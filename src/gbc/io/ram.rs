@@ -1,5 +1,7 @@
 use super::MemoryHandler;
+use super::super::state::{Stateful, State};
 
+#[allow(clippy::upper_case_acronyms)]
 pub struct HRAM {
     mem: [u8; 0xFFFE - 0xFF80 + 1],
 }
@@ -10,6 +12,10 @@ impl HRAM {
             mem: [0; 0xFFFE - 0xFF80 + 1],
         }
     }
+
+    pub fn mem(&mut self) -> &mut [u8] {
+        &mut self.mem
+    }
 }
 
 impl MemoryHandler for HRAM {
@@ -22,6 +28,7 @@ impl MemoryHandler for HRAM {
     }
 }
 
+#[allow(clippy::upper_case_acronyms)]
 pub struct WRAM {
     mem: Vec<u8>,
     bank: usize,
@@ -63,5 +70,25 @@ impl WRAM {
     pub fn read_bank(&self) -> u8 {
         if self.num_banks == 2 { 0xFF } else { self.bank as u8 }
     }
+
+    pub fn mem(&mut self) -> &mut [u8] {
+        &mut self.mem
+    }
 }
 
+impl Stateful for HRAM {
+    fn visit_state(&mut self, state: &mut State) {
+        visit!(state,
+            self.mem,
+        );
+    }
+}
+
+impl Stateful for WRAM {
+    fn visit_state(&mut self, state: &mut State) {
+        visit!(state,
+            self.mem[..], self.bank,
+        );
+        state.check(self.bank >= 1 && self.bank < self.num_banks);
+    }
+}
@@ -1,4 +1,5 @@
 use super::MemoryHandler;
+use super::super::state::{Stateful, State};
 
 pub struct Serial {
     // Registers
@@ -38,3 +39,11 @@ impl MemoryHandler for Serial {
         }
     }
 }
+
+impl Stateful for Serial {
+    fn visit_state(&mut self, state: &mut State) {
+        visit!(state,
+            self.data, self.control,
+        );
+    }
+}
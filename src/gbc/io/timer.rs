@@ -1,5 +1,6 @@
 use super::MemoryHandler;
 use super::IO;
+use super::super::state::{Stateful, State};
 
 pub struct Timer {
     // Registers
@@ -30,6 +31,10 @@ impl Timer {
         }
     }
 
+    pub fn set_divider(&mut self, divider_counter: u16) {
+        self.divider_counter = divider_counter;
+    }
+
     const CLOCK_SELECT: [usize; 4] = [9, 3, 5, 7];
 
     pub fn emulate(&mut self) -> u8 {
@@ -75,3 +80,13 @@ impl MemoryHandler for Timer {
         }
     }
 }
+
+impl Stateful for Timer {
+    fn visit_state(&mut self, state: &mut State) {
+        visit!(state,
+            self.divider_counter, self.counter, self.modulo, self.enabled, self.clock_select,
+            self.prev_counter_bit, self.overflowed, self.reloaded,
+        );
+        state.check(self.clock_select < 4);
+    }
+}
@@ -1,38 +1,317 @@
+#[macro_use]
+mod state;
 mod cpu;
 mod io;
+mod bess;
+pub mod video;
+pub mod audio;
+pub mod error;
+pub mod model;
+pub mod rewind;
+pub mod movie;
+pub mod hash;
+pub mod infrared;
+pub mod camera;
+#[cfg(test)]
+mod test_rom;
 
 use cpu::CPU;
 use io::IO;
+use state::{Stateful, State};
+pub use video::VideoSink;
+pub use audio::AudioSink;
+pub use io::Button;
+pub use error::{LoadError, StateError};
+pub use model::Model;
+pub use rewind::Rewind;
+pub use movie::Movie;
+pub use hash::FrameHashes;
+pub use infrared::InfraredLink;
+pub use camera::CameraSource;
 
 use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::vec::Drain;
 
 pub struct GBC {
     cpu: CPU,
     io: IO,
+    save_file: Option<PathBuf>, // Only set for cartridges with battery backed RAM
+    last_save: u128,
+    save_dirty: bool, // Set from the mapper, and only cleared once the save file was written
+    header: [u8; 0x1C], // 0x0134-0x014F as mapped at power on, multicarts map other headers there later
 }
 
 impl GBC {
-    pub fn new(rom_file: &String) -> Self {
-        // Stores boot rom and swapped portions of game rom
-        let mut boot_rom = fs::read("CGB_ROM.bin").unwrap();
-
+    // Uses the model from the cartridge header if none is given.
+    // Without a boot rom, emulation starts at 0x100 in the state the boot rom would have left behind.
+    // Battery backed RAM is loaded from and saved to a .sav file next to the rom.
+    pub fn new(rom_file: &Path, boot_rom_file: Option<&Path>, model: Option<Model>) -> Result<Self, LoadError> {
         let mut gbc = GBC {
             cpu: CPU::new(),
-            io: IO::new(fs::read(rom_file).unwrap()),
+            io: IO::new(GBC::read_file(rom_file)?, model)?,
+            save_file: None,
+            last_save: 0,
+            save_dirty: false,
+            header: [0; 0x1C],
         };
+        for (i, byte) in gbc.header.iter_mut().enumerate() {
+            *byte = gbc.io.read(0x0134 + i as u16);
+        }
 
-        gbc.io.swap_boot_rom(&mut boot_rom);
-        gbc.cpu.emulate_boot_rom(&mut gbc.io);
-        gbc.io.swap_boot_rom(&mut boot_rom);
+        if gbc.io.save_data().is_some() {
+            let save_file = rom_file.with_extension("sav");
+            match fs::read(&save_file) {
+                Ok(data) => gbc.io.load_save_data(&data),
+                Err(error) if error.kind() == ErrorKind::NotFound => {},
+                Err(error) => return Err(LoadError::Io(save_file, error)),
+            }
+            gbc.save_file = Some(save_file);
+        }
 
-        gbc
+        match boot_rom_file {
+            Some(boot_rom_file) => {
+                let boot_rom = GBC::read_file(boot_rom_file)?;
+                if boot_rom.len() != 0x100 && boot_rom.len() != 0x900 {
+                    return Err(LoadError::BadBootRom { size: boot_rom.len() });
+                }
+                // The boot rom runs as part of normal emulation and unmaps itself before jumping to 0x100
+                gbc.io.map_boot_rom(boot_rom);
+            },
+            None => {
+                gbc.io.skip_boot_rom();
+                gbc.cpu.skip_boot_rom(&gbc.io);
+            },
+        }
+
+        Ok(gbc)
+    }
+
+    fn read_file<P: AsRef<Path>>(path: P) -> Result<Vec<u8>, LoadError> {
+        fs::read(&path).map_err(|error| LoadError::Io(path.as_ref().to_path_buf(), error))
     }
 
+    pub const CLOCK_SPEED: u32 = 4194304;
+    pub const SCREEN_WIDTH: u32 = io::WIDTH;
+    pub const SCREEN_HEIGHT: u32 = io::HEIGHT;
+    pub const CYCLES_PER_FRAME: u32 = 456 * 154;
+    pub const SAMPLE_RATE: u32 = audio::SAMPLE_RATE;
+    const STATE_MAGIC: [u8; 4] = *b"GBCS";
+    const STATE_VERSION: u32 = 4;
+
     pub fn emulate(&mut self) {
         self.cpu.emulate(&mut self.io);
     }
+
+    // Runs until the next VBlank, or for a frame's worth of cycles if the LCD is off.
+    // Returns the last completed frame (top-down RGBA) and the number of cycles executed.
+    pub fn run_frame(&mut self) -> (&[u8], u32) {
+        self.run_cycles(GBC::CYCLES_PER_FRAME)
+    }
+
+    // Runs until the next VBlank or until at least `cycles` cycles have been executed.
+    // Samples left over from the previous run are discarded.
+    pub fn run_cycles(&mut self, cycles: u32) -> (&[u8], u32) {
+        let start = self.io.c;
+        self.io.frame_ready = false;
+        self.io.clear_samples();
+        while !self.io.frame_ready && self.io.c - start < cycles as u128 {
+            self.emulate();
+        }
+        // Saves at most once a second while battery backed RAM keeps changing, errors are reported by the next explicit save
+        if self.io.c - self.last_save >= GBC::CLOCK_SPEED as u128 {
+            self.save().ok();
+        }
+        (self.io.frame(), (self.io.c - start) as u32)
+    }
+
+    // Writes battery backed RAM, and the clock of MBC3 cartridges, to the save file if it changed since the last save
+    pub fn save(&mut self) -> std::io::Result<()> {
+        self.last_save = self.io.c;
+        self.save_dirty |= self.io.take_save_dirty();
+        if !self.save_dirty { return Ok(()) }
+        if let (Some(save_file), Some(data)) = (&self.save_file, self.io.save_data()) {
+            fs::write(save_file, data)?;
+        }
+        self.save_dirty = false;
+        Ok(())
+    }
     
-    pub fn is_running(&self) -> bool {
-        !self.io.should_close
+    // Interleaved stereo samples generated since the start of the last run
+    pub fn drain_samples(&mut self) -> Drain<'_, f32> {
+        self.io.drain_samples()
+    }
+
+    // Snapshots the complete machine state. Takes `&mut self` as saving and loading share the same code.
+    pub fn save_state(&mut self) -> Vec<u8> {
+        let mut state = State::saving();
+        self.visit_state(&mut state);
+        state.into_data()
+    }
+
+    // Restores a state from `save_state`, leaving the machine untouched if it can't be loaded
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), StateError> {
+        let mut header = State::loading(data);
+        let (mut magic, mut version, mut model, mut cartridge) = ([0u8; 4], 0u32, 0u8, [0u8; 3]);
+        visit!(header, magic, version, model, cartridge);
+        if magic != GBC::STATE_MAGIC { return Err(StateError::NotAState) }
+        if version != GBC::STATE_VERSION { return Err(StateError::UnsupportedVersion(version)) }
+        if model != self.io.model() as u8 { return Err(StateError::WrongModel) }
+        if cartridge != self.cartridge_id() { return Err(StateError::WrongCartridge) }
+
+        self.save().ok();
+        let backup = self.save_state();
+        let mut state = State::loading(data);
+        self.visit_state(&mut state);
+        if !state.finished() || !state.in_range() {
+            self.visit_state(&mut State::loading(&backup));
+            return Err(if state.finished() { StateError::OutOfRange } else { StateError::Truncated })
+        }
+        self.state_loaded();
+        Ok(())
+    }
+
+    // Restores a snapshot this machine took itself, which rewind does every few frames, so the checks, the backup
+    // and the save before loading are skipped. The restored RAM can differ from the save file, so the next
+    // periodic save writes it.
+    fn restore_state(&mut self, data: &[u8]) -> bool {
+        let mut state = State::loading(data);
+        self.visit_state(&mut state);
+        self.io.take_save_dirty();
+        self.save_dirty = true;
+        self.last_save = self.io.c;
+        state.finished() && state.in_range()
+    }
+
+    // The save file was brought up to date before loading, and keeps its contents until the game writes to
+    // the loaded RAM. The clock may have gone back, so the time until the next periodic save starts over.
+    fn state_loaded(&mut self) {
+        self.io.take_save_dirty();
+        self.save_dirty = false;
+        self.last_save = self.io.c;
+    }
+
+    // Header and global checksums, used to check that a state belongs to the loaded cartridge
+    fn cartridge_id(&self) -> [u8; 3] {
+        [self.header(0x014D), self.header(0x014E), self.header(0x014F)]
+    }
+
+    fn header(&self, addr: u16) -> u8 {
+        self.header[addr as usize - 0x0134]
+    }
+
+    pub fn set_video_sink(&mut self, video_sink: Box<dyn VideoSink>) {
+        self.io.set_video_sink(video_sink);
+    }
+
+    pub fn set_audio_sink(&mut self, audio_sink: Box<dyn AudioSink>) {
+        self.io.set_audio_sink(audio_sink);
+    }
+
+    // Only rumble cartridges call it, with true when the motor turns on and false when it turns off
+    pub fn set_rumble_callback(&mut self, rumble_callback: Box<dyn FnMut(bool)>) {
+        self.io.set_rumble_callback(rumble_callback);
+    }
+
+    // Only cartridges with an infrared LED, such as HuC1, use the link
+    pub fn set_infrared_link(&mut self, infrared_link: Box<dyn InfraredLink>) {
+        self.io.set_infrared_link(infrared_link);
+    }
+
+    // Tilt of cartridges with an accelerometer in g, positive X is right and positive Y is down
+    pub fn set_tilt(&mut self, x: f32, y: f32) {
+        self.io.set_tilt(x, y);
+    }
+
+    // Only the Pocket Camera uses the source, without one its pictures are black
+    pub fn set_camera_source(&mut self, source: Box<dyn CameraSource>) {
+        self.io.set_camera_source(source);
+    }
+
+    pub fn set_button(&mut self, button: Button, pressed: bool) {
+        self.io.set_button(button, pressed);
+    }
+
+    // Sets all buttons at once from a mask of `Button` values
+    pub fn set_buttons(&mut self, buttons: u8) {
+        self.io.set_buttons(buttons);
+    }
+
+    pub fn get_buttons(&self) -> u8 {
+        self.io.get_buttons()
+    }
+
+    pub fn get_tilt(&self) -> (f32, f32) {
+        self.io.get_tilt()
+    }
+
+    pub fn _rendering_map(&mut self, rendering_map: bool) {
+        self.io._rendering_map(rendering_map);
+    }
+}
+
+impl Stateful for GBC {
+    fn visit_state(&mut self, state: &mut State) {
+        let (mut magic, mut version) = (GBC::STATE_MAGIC, GBC::STATE_VERSION);
+        let (mut model, mut cartridge) = (self.io.model() as u8, self.cartridge_id());
+        visit!(state, magic, version, model, cartridge);
+        self.cpu.visit_state(state);
+        self.io.visit_state(state);
+    }
+}
+
+impl Drop for GBC {
+    fn drop(&mut self) {
+        self.save().ok();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::test_rom;
+
+    fn gbc(name: &str) -> GBC {
+        let mut gbc = test_rom::gbc(name, &test_rom::rom(0x01, 0x00, &test_rom::INPUT_LOOP), Model::Dmg);
+        gbc.run_frame();
+        gbc
+    }
+
+    #[test]
+    fn state_round_trips() {
+        let mut gbc = gbc("state_round_trips");
+        let state = gbc.save_state();
+        gbc.run_frame();
+        assert_ne!(gbc.save_state(), state);
+
+        gbc.load_state(&state).unwrap();
+        assert_eq!(gbc.save_state(), state);
+    }
+
+    #[test]
+    fn truncated_state_is_refused() {
+        let mut gbc = gbc("truncated_state_is_refused");
+        let state = gbc.save_state();
+        gbc.run_frame();
+        let current = gbc.save_state();
+
+        assert!(matches!(gbc.load_state(&state[..state.len() - 1]), Err(StateError::Truncated)));
+        assert_eq!(gbc.save_state(), current);
+    }
+
+    #[test]
+    fn out_of_range_state_is_refused() {
+        let mut gbc = gbc("out_of_range_state_is_refused");
+        let state = gbc.save_state();
+        // Setting the first shade of the background palette only changes that shade, which has to stay below 4
+        gbc.io.write(0xFF47, 0xE5);
+        let current = gbc.save_state();
+        let shade = state.iter().zip(current.iter()).position(|(a, b)| a != b).unwrap();
+        let mut corrupt = state.clone();
+        corrupt[shade] = 0xFF;
+
+        assert!(matches!(gbc.load_state(&corrupt), Err(StateError::OutOfRange)));
+        assert_eq!(gbc.save_state(), current);
     }
 }
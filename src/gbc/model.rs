@@ -0,0 +1,24 @@
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Model {
+    Dmg,
+    Mgb, // Game Boy Pocket
+    Cgb,
+    Agb, // Game Boy Advance running Game Boy software
+}
+
+impl Model {
+    pub fn from_name(name: &str) -> Option<Model> {
+        match name.to_ascii_lowercase().as_str() {
+            "dmg" => Some(Model::Dmg),
+            "mgb" => Some(Model::Mgb),
+            "cgb" => Some(Model::Cgb),
+            "agb" => Some(Model::Agb),
+            _ => None,
+        }
+    }
+
+    // Whether the model has CGB hardware, DMG games still run it in compatibility mode
+    pub fn is_cgb(&self) -> bool {
+        *self == Model::Cgb || *self == Model::Agb
+    }
+}
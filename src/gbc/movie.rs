@@ -0,0 +1,174 @@
+// Input movies record the joypad state and tilt of every frame together with the state the recording started
+// from, either right after power on or after loading a save state. Input only changes between frames, so
+// playing it back from the same state reproduces the recording exactly.
+
+use super::GBC;
+use super::error::StateError;
+use super::state::{Stateful, State};
+
+pub struct Movie {
+    start_state: Vec<u8>,
+    inputs: Vec<u8>, // Mask of `Button` values for each frame
+    tilts: Vec<[f32; 2]>, // Accelerometer X and Y for each frame
+    frame: usize,
+    playing: bool,
+}
+
+impl Movie {
+    const MAGIC: [u8; 4] = *b"GBCM";
+    const VERSION: u32 = 2;
+
+    // Starts recording from the current state of `gbc`
+    pub fn record(gbc: &mut GBC) -> Self {
+        Movie { start_state: gbc.save_state(), inputs: Vec::new(), tilts: Vec::new(), frame: 0, playing: false }
+    }
+
+    // Reads a movie written by `to_bytes`, the start state is checked against the machine by `play`
+    pub fn from_bytes(data: &[u8]) -> Result<Self, StateError> {
+        let mut movie = Movie { start_state: Vec::new(), inputs: Vec::new(), tilts: Vec::new(), frame: 0, playing: true };
+        let mut state = State::loading(data);
+        let (mut magic, mut version) = ([0u8; 4], 0u32);
+        visit!(state, magic, version);
+        if magic != Movie::MAGIC { return Err(StateError::NotAState) }
+        if version != Movie::VERSION { return Err(StateError::UnsupportedVersion(version)) }
+
+        let mut state = State::loading(data);
+        movie.visit_state(&mut state);
+        if !state.finished() { return Err(StateError::Truncated) }
+        if movie.tilts.len() != movie.inputs.len() { return Err(StateError::OutOfRange) }
+        Ok(movie)
+    }
+
+    pub fn to_bytes(&mut self) -> Vec<u8> {
+        let mut state = State::saving();
+        self.visit_state(&mut state);
+        state.into_data()
+    }
+
+    // Restores the start state and plays the inputs back from the first frame
+    pub fn play(&mut self, gbc: &mut GBC) -> Result<(), StateError> {
+        gbc.load_state(&self.start_state)?;
+        self.frame = 0;
+        self.playing = true;
+        Ok(())
+    }
+
+    // Call before every frame. Records the current buttons and tilt, or sets them while playing.
+    // Returns false once playback has run out of inputs, leaving the input as it is.
+    pub fn update(&mut self, gbc: &mut GBC) -> bool {
+        if self.playing {
+            match (self.inputs.get(self.frame), self.tilts.get(self.frame)) {
+                (Some(buttons), Some([tilt_x, tilt_y])) => {
+                    gbc.set_buttons(*buttons);
+                    gbc.set_tilt(*tilt_x, *tilt_y);
+                },
+                _ => return false,
+            }
+        } else {
+            let (tilt_x, tilt_y) = gbc.get_tilt();
+            self.inputs.push(gbc.get_buttons());
+            self.tilts.push([tilt_x, tilt_y]);
+        }
+        self.frame += 1;
+        true
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.playing
+    }
+
+    // Number of recorded frames
+    pub fn len(&self) -> usize {
+        self.inputs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inputs.is_empty()
+    }
+}
+
+impl Stateful for Movie {
+    fn visit_state(&mut self, state: &mut State) {
+        let (mut magic, mut version) = (Movie::MAGIC, Movie::VERSION);
+        visit!(state, magic, version, self.start_state, self.inputs, self.tilts);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::{test_rom, FrameHashes, Model};
+
+    // Runs `frames` frames with changing buttons, updating `movie` before each one
+    fn run(gbc: &mut GBC, movie: &mut Movie, frames: u8) -> Vec<FrameHashes> {
+        (0..frames).map(|frame| {
+            gbc.set_buttons(frame.wrapping_mul(37));
+            movie.update(gbc);
+            gbc.run_frame();
+            gbc.frame_hashes()
+        }).collect()
+    }
+
+    #[test]
+    fn bytes_round_trip() {
+        let rom = test_rom::rom(0x01, 0x00, &test_rom::INPUT_LOOP);
+        let mut gbc = test_rom::gbc("bytes_round_trip", &rom, Model::Dmg);
+        let mut movie = Movie::record(&mut gbc);
+        run(&mut gbc, &mut movie, 10);
+
+        let data = movie.to_bytes();
+        let mut loaded = Movie::from_bytes(&data).unwrap();
+        assert!(loaded.is_playing());
+        assert_eq!(loaded.len(), 10);
+        assert_eq!(loaded.to_bytes(), data);
+        assert!(matches!(Movie::from_bytes(&data[..data.len() - 1]), Err(StateError::Truncated)));
+    }
+
+    #[test]
+    fn playback_reproduces_recording() {
+        let rom = test_rom::rom(0x01, 0x00, &test_rom::INPUT_LOOP);
+        let mut gbc = test_rom::gbc("playback_reproduces_recording", &rom, Model::Dmg);
+        gbc.run_frame();
+        let mut movie = Movie::record(&mut gbc);
+        let recorded = run(&mut gbc, &mut movie, 30);
+
+        let mut movie = Movie::from_bytes(&movie.to_bytes()).unwrap();
+        gbc.set_buttons(0);
+        movie.play(&mut gbc).unwrap();
+        let played: Vec<FrameHashes> = (0..30).map(|_| {
+            assert!(movie.update(&mut gbc));
+            gbc.run_frame();
+            gbc.frame_hashes()
+        }).collect();
+        assert_eq!(played, recorded);
+        assert!(!movie.update(&mut gbc));
+    }
+
+    #[test]
+    fn tilt_round_trips_and_is_hashed() {
+        let rom = test_rom::rom(0x22, 0x00, &test_rom::INPUT_LOOP);
+        let mut gbc = test_rom::gbc("tilt_round_trips_and_is_hashed", &rom, Model::Dmg);
+        gbc.run_frame();
+        let start = gbc.save_state();
+        let level = gbc.frame_hashes();
+        gbc.set_tilt(0.5, -0.25);
+        assert_ne!(gbc.frame_hashes().memory, level.memory);
+
+        gbc.load_state(&start).unwrap();
+        let mut movie = Movie::record(&mut gbc);
+        for frame in 0..5 {
+            gbc.set_tilt(frame as f32 / 10.0, -0.25);
+            movie.update(&mut gbc);
+            gbc.run_frame();
+        }
+
+        let mut movie = Movie::from_bytes(&movie.to_bytes()).unwrap();
+        gbc.set_tilt(0.0, 0.0);
+        movie.play(&mut gbc).unwrap();
+        for frame in 0..5 {
+            movie.update(&mut gbc);
+            assert_eq!(gbc.get_tilt(), (frame as f32 / 10.0, -0.25));
+            gbc.run_frame();
+        }
+    }
+}
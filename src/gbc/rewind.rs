@@ -0,0 +1,173 @@
+// Rewind history of periodic save states. Only the newest snapshot is kept in full, every older one is
+// stored as the XOR against the snapshot after it, run-length encoded since most of the machine state
+// doesn't change between snapshots. Stepping back undoes one delta, and the oldest deltas are dropped
+// once the history is over its memory budget.
+
+use super::GBC;
+
+use std::collections::VecDeque;
+
+pub struct Rewind {
+    interval: u32, // Frames between snapshots
+    capacity: usize, // Bytes used by the history before old snapshots are dropped
+    frames: u32, // Frames recorded since the newest snapshot
+    newest: Vec<u8>,
+    deltas: VecDeque<Vec<u8>>,
+    size: usize,
+}
+
+impl Rewind {
+    // A snapshot every 4 frames in 64 MiB holds several minutes of most games
+    pub const DEFAULT_INTERVAL: u32 = 4;
+    pub const DEFAULT_CAPACITY: usize = 64 << 20;
+
+    pub fn new(interval: u32, capacity: usize) -> Self {
+        Rewind {
+            interval: interval.max(1),
+            capacity,
+            frames: 0,
+            newest: Vec::new(),
+            deltas: VecDeque::new(),
+            size: 0,
+        }
+    }
+
+    // Call after every frame that runs forward, takes a snapshot every `interval` frames
+    pub fn record(&mut self, gbc: &mut GBC) {
+        self.frames += 1;
+        if self.frames < self.interval && !self.newest.is_empty() { return }
+        self.frames = 0;
+
+        let state = gbc.save_state();
+        if !self.newest.is_empty() {
+            let delta = encode(&self.newest, &state);
+            self.size += delta.len();
+            self.deltas.push_back(delta);
+        }
+        self.size = self.size - self.newest.len() + state.len();
+        self.newest = state;
+
+        while self.size > self.capacity {
+            match self.deltas.pop_front() {
+                Some(delta) => self.size -= delta.len(),
+                None => break,
+            }
+        }
+    }
+
+    // Goes back to the newest snapshot if frames ran since it was taken, otherwise to the one before it.
+    // Returns false once the history runs out.
+    pub fn rewind(&mut self, gbc: &mut GBC) -> bool {
+        if self.newest.is_empty() { return false }
+        if self.frames == 0 {
+            let delta = match self.deltas.pop_back() {
+                Some(delta) => delta,
+                None => return false,
+            };
+            let older = decode(&self.newest, &delta);
+            self.size = self.size - delta.len() - self.newest.len() + older.len();
+            self.newest = older;
+        }
+        self.frames = 0;
+        gbc.restore_state(&self.newest)
+    }
+
+    pub fn clear(&mut self) {
+        self.frames = 0;
+        self.newest.clear();
+        self.deltas.clear();
+        self.size = 0;
+    }
+
+    // Number of snapshots that can be stepped back to
+    pub fn len(&self) -> usize {
+        if self.newest.is_empty() { 0 } else { self.deltas.len() + 1 }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.newest.is_empty()
+    }
+}
+
+// Encodes `older ^ newer` as the length of `older` followed by pairs of zero and literal run lengths,
+// each literal run followed by its bytes. `newer` is treated as zero padded past its end.
+fn encode(older: &[u8], newer: &[u8]) -> Vec<u8> {
+    let diff = |i: usize| older[i] ^ newer.get(i).copied().unwrap_or(0);
+    let mut delta = Vec::new();
+    write_length(&mut delta, older.len());
+
+    let mut i = 0;
+    while i < older.len() {
+        let zeros_start = i;
+        while i < older.len() && diff(i) == 0 { i += 1 }
+        let literal_start = i;
+        while i < older.len() && diff(i) != 0 { i += 1 }
+        write_length(&mut delta, literal_start - zeros_start);
+        write_length(&mut delta, i - literal_start);
+        delta.extend((literal_start..i).map(diff));
+    }
+    delta
+}
+
+fn decode(newer: &[u8], delta: &[u8]) -> Vec<u8> {
+    let mut pos = 0;
+    let len = read_length(delta, &mut pos);
+    let mut older: Vec<u8> = (0..len).map(|i| newer.get(i).copied().unwrap_or(0)).collect();
+
+    let mut i = 0;
+    while pos < delta.len() {
+        i += read_length(delta, &mut pos);
+        let literal_len = read_length(delta, &mut pos);
+        for (byte, diff) in older[i..i + literal_len].iter_mut().zip(&delta[pos..pos + literal_len]) {
+            *byte ^= diff;
+        }
+        i += literal_len;
+        pos += literal_len;
+    }
+    older
+}
+
+// Lengths are LEB128 encoded, so short runs take a single byte
+fn write_length(data: &mut Vec<u8>, mut len: usize) {
+    while len >= 0x80 {
+        data.push(len as u8 | 0x80);
+        len >>= 7;
+    }
+    data.push(len as u8);
+}
+
+fn read_length(data: &[u8], pos: &mut usize) -> usize {
+    let (mut len, mut shift) = (0, 0);
+    loop {
+        let byte = data[*pos];
+        *pos += 1;
+        len |= ((byte & 0x7F) as usize) << shift;
+        if byte & 0x80 == 0 { return len }
+        shift += 7;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deltas_decode_to_the_older_snapshot() {
+        // Runs longer than 127 bytes need more than one byte for their length, and the size can change between snapshots
+        let mut snapshots = vec![vec![0u8; 1000]];
+        for i in 1..8u8 {
+            let mut snapshot = snapshots[i as usize - 1].clone();
+            snapshot[i as usize * 3] = i;
+            snapshot[200 + i as usize * 100..300 + i as usize * 100].iter_mut().for_each(|byte| *byte ^= 0x5A);
+            snapshot.resize(1000 + i as usize % 3 * 10, i);
+            snapshots.push(snapshot);
+        }
+
+        let deltas: Vec<Vec<u8>> = snapshots.windows(2).map(|pair| encode(&pair[0], &pair[1])).collect();
+        let mut newer = snapshots.last().unwrap().clone();
+        for (delta, older) in deltas.iter().zip(snapshots.iter()).rev() {
+            newer = decode(&newer, delta);
+            assert_eq!(&newer, older);
+        }
+    }
+}
@@ -0,0 +1,180 @@
+// Save states are built by visiting every piece of machine state in a fixed order.
+// Saving and loading share the same visit code, so the two layouts can't drift apart.
+
+macro_rules! visit {
+    ($state:expr, $($field:expr),* $(,)?) => {
+        $( $state.visit(&mut $field); )*
+    }
+}
+
+pub trait Stateful {
+    fn visit_state(&mut self, state: &mut State);
+}
+
+pub trait StateValue {
+    fn save(&self, state: &mut State);
+    fn load(&mut self, state: &mut State);
+}
+
+pub struct State {
+    data: Vec<u8>,
+    pos: usize,
+    loading: bool,
+    failed: bool, // Set when loading runs past the end of the data
+    out_of_range: bool, // Set when a loaded value fails a check
+}
+
+impl State {
+    pub fn saving() -> Self {
+        State { data: Vec::new(), pos: 0, loading: false, failed: false, out_of_range: false }
+    }
+
+    pub fn loading(data: &[u8]) -> Self {
+        State { data: data.to_vec(), pos: 0, loading: true, failed: false, out_of_range: false }
+    }
+
+    pub fn is_loading(&self) -> bool {
+        self.loading
+    }
+
+    pub fn visit<T: StateValue + ?Sized>(&mut self, value: &mut T) {
+        if self.loading { value.load(self) } else { value.save(self) }
+    }
+
+    // Whether all of the loaded data was used without running out
+    pub fn finished(&self) -> bool {
+        !self.failed && self.pos == self.data.len()
+    }
+
+    // Rejects a loaded state when `in_range` is false. Values used as indices are checked this way, so a corrupt
+    // state is refused when it is loaded instead of panicking later.
+    pub fn check(&mut self, in_range: bool) {
+        if self.loading && !in_range { self.out_of_range = true }
+    }
+
+    pub fn in_range(&self) -> bool {
+        !self.out_of_range
+    }
+
+    pub fn into_data(self) -> Vec<u8> {
+        self.data
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        self.data.extend_from_slice(bytes);
+    }
+
+    fn read(&mut self, len: usize) -> Option<&[u8]> {
+        if self.failed || self.data.len() - self.pos < len {
+            self.failed = true;
+            return None
+        }
+        self.pos += len;
+        Some(&self.data[self.pos - len..self.pos])
+    }
+
+    fn remaining(&self) -> usize {
+        self.data.len() - self.pos
+    }
+}
+
+macro_rules! int_state_value {
+    ($($int:ty),*) => {$(
+        impl StateValue for $int {
+            fn save(&self, state: &mut State) {
+                state.write(&self.to_le_bytes());
+            }
+
+            fn load(&mut self, state: &mut State) {
+                let mut bytes = [0; std::mem::size_of::<$int>()];
+                if let Some(data) = state.read(bytes.len()) {
+                    bytes.copy_from_slice(data);
+                    *self = <$int>::from_le_bytes(bytes);
+                }
+            }
+        }
+    )*}
+}
+
+int_state_value!(u8, u16, u32, u64, u128, i8, i16, i32, f32);
+
+impl StateValue for usize {
+    fn save(&self, state: &mut State) {
+        (*self as u64).save(state);
+    }
+
+    fn load(&mut self, state: &mut State) {
+        let mut value = 0u64;
+        value.load(state);
+        *self = value as usize;
+    }
+}
+
+impl StateValue for bool {
+    fn save(&self, state: &mut State) {
+        (*self as u8).save(state);
+    }
+
+    fn load(&mut self, state: &mut State) {
+        let mut value = 0u8;
+        value.load(state);
+        *self = value != 0;
+    }
+}
+
+// Slices and arrays have a fixed length that isn't stored
+impl<T: StateValue> StateValue for [T] {
+    fn save(&self, state: &mut State) {
+        for value in self.iter() { value.save(state) }
+    }
+
+    fn load(&mut self, state: &mut State) {
+        for value in self.iter_mut() { value.load(state) }
+    }
+}
+
+impl<T: StateValue, const N: usize> StateValue for [T; N] {
+    fn save(&self, state: &mut State) {
+        self[..].save(state);
+    }
+
+    fn load(&mut self, state: &mut State) {
+        self[..].load(state);
+    }
+}
+
+impl<T: StateValue + Default + Clone> StateValue for Vec<T> {
+    fn save(&self, state: &mut State) {
+        (self.len() as u32).save(state);
+        for value in self.iter() { value.save(state) }
+    }
+
+    fn load(&mut self, state: &mut State) {
+        let mut len = 0u32;
+        len.load(state);
+        if len as usize > state.remaining() {
+            state.failed = true;
+            return
+        }
+        self.clear();
+        self.resize(len as usize, T::default());
+        for value in self.iter_mut() { value.load(state) }
+    }
+}
+
+impl<T: StateValue + Default> StateValue for Option<T> {
+    fn save(&self, state: &mut State) {
+        self.is_some().save(state);
+        if let Some(value) = self { value.save(state) }
+    }
+
+    fn load(&mut self, state: &mut State) {
+        let mut is_some = false;
+        is_some.load(state);
+        *self = if is_some {
+            let mut value = T::default();
+            value.load(state);
+            Some(value)
+        } else { None };
+    }
+}
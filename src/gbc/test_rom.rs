@@ -0,0 +1,40 @@
+// Small generated cartridges for the unit tests, so they don't depend on rom files on disk
+
+use super::{GBC, Model};
+
+use std::fs;
+use std::path::PathBuf;
+
+// Reads both joypad groups into 0xC000 and 0xC001 and counts up 0xC002, forever
+pub const INPUT_LOOP: [u8; 24] = [
+    0x3E, 0x10, 0xE0, 0x00, // LD A, 0x10; LDH (0x00), A
+    0xF0, 0x00, 0xEA, 0x00, 0xC0, // LDH A, (0x00); LD (0xC000), A
+    0x3E, 0x20, 0xE0, 0x00, // LD A, 0x20; LDH (0x00), A
+    0xF0, 0x00, 0xEA, 0x01, 0xC0, // LDH A, (0x00); LD (0xC001), A
+    0x21, 0x02, 0xC0, 0x34, // LD HL, 0xC002; INC (HL)
+    0x18, 0xE8, // JR to the start
+];
+
+// A 32KB rom with a valid header, starting `code` at 0x150
+pub fn rom(cartridge_type: u8, ram_size: u8, code: &[u8]) -> Vec<u8> {
+    let mut rom = vec![0; 0x8000];
+    rom[0x100..0x104].copy_from_slice(&[0x00, 0xC3, 0x50, 0x01]);
+    rom[0x134..0x138].copy_from_slice(b"TEST");
+    rom[0x147] = cartridge_type;
+    rom[0x149] = ram_size;
+    rom[0x14D] = rom[0x134..0x14D].iter().fold(0u8, |x, byte| x.wrapping_add(!byte));
+    rom[0x150..0x150 + code.len()].copy_from_slice(code);
+    rom
+}
+
+// Boots `rom` without a boot rom from a file named after the test, with no save file left from earlier runs
+pub fn gbc(name: &str, rom: &[u8], model: Model) -> GBC {
+    let rom_file = rom_file(name);
+    fs::write(&rom_file, rom).unwrap();
+    fs::remove_file(rom_file.with_extension("sav")).ok();
+    GBC::new(&rom_file, None, Some(model)).unwrap()
+}
+
+pub fn rom_file(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("gbc-test-{}-{}.gb", std::process::id(), name))
+}
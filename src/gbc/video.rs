@@ -0,0 +1,56 @@
+use std::cell::{Cell, Ref, RefCell};
+use std::rc::Rc;
+
+// Receives every completed frame from the PPU as top-down RGBA
+pub trait VideoSink {
+    fn present(&mut self, frame: &[u8]);
+
+    fn set_double_speed(&mut self, _double_speed: bool) {}
+}
+
+// Discards all frames
+pub struct NullSink;
+
+impl VideoSink for NullSink {
+    fn present(&mut self, _frame: &[u8]) {}
+}
+
+// Keeps the latest frame in memory. Clones share the same buffer, so one clone
+// can be handed to the emulator while another is used to read the frames.
+#[derive(Clone)]
+pub struct BufferSink {
+    frame: Rc<RefCell<Vec<u8>>>,
+    frame_count: Rc<Cell<u64>>,
+}
+
+impl BufferSink {
+    pub fn new() -> Self {
+        BufferSink {
+            frame: Rc::new(RefCell::new(Vec::new())),
+            frame_count: Rc::new(Cell::new(0)),
+        }
+    }
+
+    pub fn frame(&self) -> Ref<'_, Vec<u8>> {
+        self.frame.borrow()
+    }
+
+    pub fn frame_count(&self) -> u64 {
+        self.frame_count.get()
+    }
+}
+
+impl Default for BufferSink {
+    fn default() -> Self {
+        BufferSink::new()
+    }
+}
+
+impl VideoSink for BufferSink {
+    fn present(&mut self, frame: &[u8]) {
+        let mut buffer = self.frame.borrow_mut();
+        buffer.clear();
+        buffer.extend_from_slice(frame);
+        self.frame_count.set(self.frame_count.get() + 1);
+    }
+}
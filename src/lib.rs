@@ -1 +1,3 @@
 pub mod gbc;
+#[cfg(feature = "sdl")]
+pub mod frontend;
@@ -1,11 +1,262 @@
-pub mod gbc;
+use gbc_emulator::gbc::{GBC, Model, Movie};
+use gbc_emulator::gbc::camera::{ImageFile, ImageDirectory};
+#[cfg(feature = "sdl")]
+use gbc_emulator::frontend::Frontend;
 
-use gbc::GBC;
+use std::env;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+
+const USAGE: &str = "\
+Usage: gbc-emulator [OPTIONS] <ROM>
+
+Options:
+    --boot-rom <PATH>     Boot ROM image to run before the game [default: none, start at 0x100]
+    --model <MODEL>       Hardware model to emulate: dmg, mgb, cgb or agb [default: from the cartridge header]
+    --state <PATH>        Save state to start from, in this emulator's or the BESS format
+    --record-movie <PATH> Record the joypad input and tilt of every frame to a movie file
+    --play-movie <PATH>   Play back a movie file, from the state its recording started at
+    --hash-log <PATH>     Write hashes of the frame, audio and machine state after every frame
+    --camera <PATH>       Image, or directory of images taken in turn, the Pocket Camera sees (PGM, PPM or BMP)
+    --scale <N>           Window scale [default: 3]
+    --mute                Disable audio output
+    --headless <FRAMES>   Run for FRAMES frames without a window and exit
+    -h, --help            Print this message";
+
+struct Options {
+    rom_file: PathBuf,
+    boot_rom_file: Option<PathBuf>,
+    model: Option<Model>,
+    state_file: Option<PathBuf>,
+    record_movie_file: Option<PathBuf>,
+    play_movie_file: Option<PathBuf>,
+    hash_log_file: Option<PathBuf>,
+    camera_path: Option<PathBuf>,
+    scale: u32,
+    mute: bool,
+    headless_frames: Option<u32>,
+}
+
+impl Options {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut rom_file = None;
+        let mut options = Options {
+            rom_file: PathBuf::new(),
+            boot_rom_file: None,
+            model: None,
+            state_file: None,
+            record_movie_file: None,
+            play_movie_file: None,
+            hash_log_file: None,
+            camera_path: None,
+            scale: 3,
+            mute: false,
+            headless_frames: None,
+        };
+
+        while let Some(arg) = args.next() {
+            let mut value = |name: &str| args.next().ok_or(format!("Missing value for {}", name));
+            match arg.as_str() {
+                "-h" | "--help" => {
+                    println!("{}", USAGE);
+                    process::exit(0);
+                },
+                "--boot-rom" => options.boot_rom_file = Some(PathBuf::from(value(&arg)?)),
+                "--model" => {
+                    let model = value(&arg)?;
+                    options.model = Some(Model::from_name(&model).ok_or(format!("Unknown model {}", model))?);
+                },
+                "--state" => options.state_file = Some(PathBuf::from(value(&arg)?)),
+                "--record-movie" => options.record_movie_file = Some(PathBuf::from(value(&arg)?)),
+                "--play-movie" => options.play_movie_file = Some(PathBuf::from(value(&arg)?)),
+                "--hash-log" => options.hash_log_file = Some(PathBuf::from(value(&arg)?)),
+                "--camera" => options.camera_path = Some(PathBuf::from(value(&arg)?)),
+                "--scale" => {
+                    let scale = value(&arg)?;
+                    options.scale = match scale.parse() {
+                        Ok(scale) if scale > 0 => scale,
+                        _ => return Err(format!("Invalid scale {}", scale)),
+                    };
+                },
+                "--mute" => options.mute = true,
+                "--headless" => {
+                    let frames = value(&arg)?;
+                    options.headless_frames = Some(frames.parse().map_err(|_| format!("Invalid frame count {}", frames))?);
+                },
+                _ if arg.starts_with('-') => return Err(format!("Unknown option {}", arg)),
+                _ if rom_file.is_none() => rom_file = Some(PathBuf::from(arg)),
+                _ => return Err(format!("Unexpected argument {}", arg)),
+            }
+        }
+
+        options.rom_file = rom_file.ok_or("Missing ROM path")?;
+        if options.play_movie_file.is_some() && (options.state_file.is_some() || options.record_movie_file.is_some()) {
+            return Err("--play-movie can't be combined with --state or --record-movie".to_string());
+        }
+        Ok(options)
+    }
+}
 
 fn main() {
-    std::env::set_current_dir("ROMs").unwrap();
-    let mut gbc = GBC::new(&"cpu_instrs/cpu_instrs.gb".to_string());
-    while gbc.is_running() {
-        gbc.emulate();
+    let options = match Options::parse(env::args().skip(1)) {
+        Ok(options) => options,
+        Err(error) => {
+            eprintln!("{}\n\n{}", error, USAGE);
+            process::exit(2);
+        },
+    };
+
+    let mut gbc = match GBC::new(&options.rom_file, options.boot_rom_file.as_deref(), options.model) {
+        Ok(gbc) => gbc,
+        Err(error) => {
+            eprintln!("{}", error);
+            process::exit(1);
+        },
+    };
+
+    if let Some(camera_path) = &options.camera_path {
+        let source = if camera_path.is_dir() {
+            ImageDirectory::open(camera_path).map(|source| Box::new(source) as Box<_>)
+        } else {
+            ImageFile::open(camera_path).map(|source| Box::new(source) as Box<_>)
+        };
+        match source {
+            Ok(source) => gbc.set_camera_source(source),
+            Err(error) => {
+                eprintln!("Failed to open camera images {}: {}", camera_path.display(), error);
+                process::exit(1);
+            },
+        }
     }
+
+    if let Some(state_file) = &options.state_file {
+        if let Err(error) = load_state(&mut gbc, state_file) {
+            eprintln!("Failed to load state {}: {}", state_file.display(), error);
+            process::exit(1);
+        }
+    }
+
+    // Recording starts from power on, or from the state loaded above
+    let movie = match &options.play_movie_file {
+        Some(movie_file) => match play_movie(&mut gbc, movie_file) {
+            Ok(movie) => Some(movie),
+            Err(error) => {
+                eprintln!("Failed to play movie {}: {}", movie_file.display(), error);
+                process::exit(1);
+            },
+        },
+        None if options.record_movie_file.is_some() => Some(Movie::record(&mut gbc)),
+        None => None,
+    };
+
+    let hash_log = match &options.hash_log_file {
+        Some(hash_log_file) => match File::create(hash_log_file) {
+            Ok(file) => Some(BufWriter::new(file)),
+            Err(error) => {
+                eprintln!("Failed to create hash log {}: {}", hash_log_file.display(), error);
+                process::exit(1);
+            },
+        },
+        None => None,
+    };
+
+    let mut session = Session { movie, hash_log, frame: 0 };
+    match options.headless_frames {
+        Some(frames) => run_headless(&mut gbc, &mut session, frames),
+        None => run_windowed(&mut gbc, &mut session, &options),
+    }
+
+    if let Some(hash_log) = &mut session.hash_log {
+        if let Err(error) = hash_log.flush() {
+            eprintln!("Failed to write hash log: {}", error);
+            process::exit(1);
+        }
+    }
+
+    if let (Some(movie_file), Some(movie)) = (&options.record_movie_file, &mut session.movie) {
+        if let Err(error) = fs::write(movie_file, movie.to_bytes()) {
+            eprintln!("Failed to write movie {}: {}", movie_file.display(), error);
+            process::exit(1);
+        }
+    }
+
+    if let Err(error) = gbc.save() {
+        eprintln!("Failed to write save file: {}", error);
+        process::exit(1);
+    }
+}
+
+fn load_state(gbc: &mut GBC, state_file: &Path) -> Result<(), String> {
+    let data = fs::read(state_file).map_err(|error| error.to_string())?;
+    let result = if data.ends_with(b"BESS") { gbc.load_bess(&data) } else { gbc.load_state(&data) };
+    result.map_err(|error| error.to_string())
+}
+
+fn play_movie(gbc: &mut GBC, movie_file: &Path) -> Result<Movie, String> {
+    let data = fs::read(movie_file).map_err(|error| error.to_string())?;
+    let mut movie = Movie::from_bytes(&data).map_err(|error| error.to_string())?;
+    movie.play(gbc).map_err(|error| error.to_string())?;
+    Ok(movie)
+}
+
+// Per-frame work around emulation shared by the headless and windowed loops
+struct Session {
+    movie: Option<Movie>,
+    hash_log: Option<BufWriter<File>>,
+    frame: u64,
+}
+
+impl Session {
+    // Applies movie input before a frame, returns false once a movie being played back runs out
+    fn before_frame(&mut self, gbc: &mut GBC) -> bool {
+        match &mut self.movie {
+            Some(movie) => movie.update(gbc),
+            None => true,
+        }
+    }
+
+    // Logging stops at the first write error
+    fn after_frame(&mut self, gbc: &mut GBC) {
+        if let Some(hash_log) = &mut self.hash_log {
+            if writeln!(hash_log, "frame={} {}", self.frame, gbc.frame_hashes()).is_err() {
+                self.hash_log = None;
+                eprintln!("Failed to write hash log");
+            }
+        }
+        self.frame += 1;
+    }
+}
+
+fn run_headless(gbc: &mut GBC, session: &mut Session, frames: u32) {
+    for _ in 0..frames {
+        if !session.before_frame(gbc) { break }
+        gbc.run_frame();
+        session.after_frame(gbc);
+    }
+}
+
+#[cfg(feature = "sdl")]
+fn run_windowed(gbc: &mut GBC, session: &mut Session, options: &Options) {
+    let mut frontend = Frontend::new(&options.rom_file);
+    gbc.set_video_sink(Box::new(frontend.screen(options.scale)));
+    if !options.mute {
+        gbc.set_audio_sink(Box::new(frontend.audio()));
+    }
+    while frontend.is_running() {
+        frontend.handle_events(gbc, session.movie.as_ref());
+        if !session.before_frame(gbc) {
+            println!("Movie finished");
+            session.movie = None;
+        }
+        frontend.run_frame(gbc);
+        session.after_frame(gbc);
+    }
+}
+
+#[cfg(not(feature = "sdl"))]
+fn run_windowed(_gbc: &mut GBC, _session: &mut Session, _options: &Options) {
+    eprintln!("Built without the sdl feature, so only --headless is available");
+    process::exit(1);
 }